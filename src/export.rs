@@ -0,0 +1,435 @@
+// Copyright (c) 2025 Philip Taylor
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// Exporters that write the annotations in other formats
+
+use std::{collections::BTreeMap, fs::File, io::{BufWriter, Write}};
+
+use eframe::epaint::{Pos2, Rect};
+
+use crate::{Article, ArticleKind, State};
+
+const STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been", "before", "but", "by",
+    "can", "could", "did", "do", "for", "from", "had", "has", "have", "he", "her", "him", "his", "i", "if", "in",
+    "into", "is", "it", "its", "last", "may", "more", "most", "mr", "new", "no", "not", "now", "of", "on", "one",
+    "only", "or", "other", "our", "out", "over", "said", "she", "should", "so", "some", "than", "that", "the",
+    "their", "them", "then", "there", "these", "they", "this", "those", "to", "two", "up", "was", "we", "were",
+    "what", "when", "which", "who", "will", "with", "would", "you",
+];
+
+// Articles on the given pages that are part of the transcription (not [NOTE]s or images)
+fn content_articles<'a>(state: &'a State, images: &'a [String]) -> impl Iterator<Item = &'a Article> {
+    images.iter()
+        .filter_map(|filename| state.pages.get(filename))
+        .flat_map(|page| &page.articles)
+        .filter(|article| article.kind == ArticleKind::Text && !article.text.starts_with("[NOTE]"))
+}
+
+// Split into words, dropping punctuation but keeping internal apostrophes and hyphens.
+// Each word is paired with whether it starts a sentence
+fn words(text: &str) -> Vec<(&str, bool)> {
+    let mut words = Vec::new();
+    for line in text.lines() {
+        let mut sentence_start = true;
+        for token in line.split_whitespace() {
+            let word = token.trim_matches(|c: char| !c.is_alphanumeric());
+            if !word.is_empty() && word.chars().any(|c| c.is_alphabetic()) {
+                words.push((word, sentence_start));
+            }
+            if token == "#" {
+                continue;
+            }
+            sentence_start = token.ends_with(['.', '!', '?', ':']);
+        }
+    }
+    words
+}
+
+// Write a word frequency list and a list of capitalised words (candidate index entries),
+// each as tab-separated "count word" lines sorted by decreasing count
+pub(crate) fn export_word_index(state: &State, images: &[String], frequency_path: &str, names_path: &str) -> std::io::Result<()> {
+    let mut frequency: BTreeMap<String, usize> = BTreeMap::new();
+    let mut names: BTreeMap<String, usize> = BTreeMap::new();
+    for article in content_articles(state, images) {
+        for (word, sentence_start) in words(&article.text) {
+            let lower = word.to_lowercase();
+            if STOPWORDS.contains(&lower.as_str()) {
+                continue;
+            }
+            // Capitalised words at the start of a sentence don't tell us anything
+            if !sentence_start && word.starts_with(|c: char| c.is_uppercase()) {
+                *names.entry(word.to_string()).or_default() += 1;
+            }
+            *frequency.entry(lower).or_default() += 1;
+        }
+    }
+
+    for (path, counts) in [(frequency_path, frequency), (names_path, names)] {
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let mut out = BufWriter::new(File::create(path)?);
+        for (word, count) in counts {
+            writeln!(out, "{}\t{}", count, word)?;
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+fn alto_coords(rect: Rect) -> String {
+    format!(
+        "HPOS=\"{}\" VPOS=\"{}\" WIDTH=\"{}\" HEIGHT=\"{}\"",
+        rect.left().round(), rect.top().round(), rect.width().round(), rect.height().round()
+    )
+}
+
+fn write_alto_strings(out: &mut impl Write, text: &str) -> std::io::Result<()> {
+    for (i, word) in text.split_whitespace().enumerate() {
+        if i > 0 {
+            write!(out, "<SP/>")?;
+        }
+        write!(out, "<String CONTENT=\"{}\"/>", xml_escape(word))?;
+    }
+    Ok(())
+}
+
+fn write_alto_block(out: &mut impl Write, article: &Article, language: &str) -> std::io::Result<()> {
+    let Some(bbox) = article.polys.iter().flatten().map(|&p| Rect::from_min_max(p, p)).reduce(Rect::union) else {
+        return Ok(());
+    };
+    let lang = if language.is_empty() { String::new() } else { format!(" LANG=\"{}\"", xml_escape(language)) };
+    writeln!(out, "        <TextBlock ID=\"block_{}\" {}{}>", article.id, alto_coords(bbox), lang)?;
+
+    // If we have the OCR lines for every poly, we know where each line is, but otherwise
+    // all we can do is give the transcribed text's lines without any geometry
+    let raw_ocr: Option<Vec<_>> = (0..article.polys.len()).map(|i| article.raw_ocr.get(i)?.as_ref()).collect();
+    let mut line_id = 0;
+    match raw_ocr {
+        Some(raw_ocr) if raw_ocr.iter().all(|ocr| ocr.crop.is_some()) => {
+            for ocr in raw_ocr {
+                let crop = ocr.crop.unwrap();
+                for line in &ocr.lines {
+                    let rect = Rect::from_min_max(
+                        crop.min + line.bbox.min.to_vec2() * crop.size(),
+                        crop.min + line.bbox.max.to_vec2() * crop.size(),
+                    );
+                    write!(out, "          <TextLine ID=\"line_{}_{}\" {}>", article.id, line_id, alto_coords(rect))?;
+                    write_alto_strings(out, &line.text)?;
+                    writeln!(out, "</TextLine>")?;
+                    line_id += 1;
+                }
+            }
+        }
+        _ => {
+            for line in article.text.lines().map(|line| line.trim_start_matches('#').trim()).filter(|line| !line.is_empty()) {
+                write!(out, "          <TextLine ID=\"line_{}_{}\">", article.id, line_id)?;
+                write_alto_strings(out, line)?;
+                writeln!(out, "</TextLine>")?;
+                line_id += 1;
+            }
+        }
+    }
+
+    writeln!(out, "        </TextBlock>")
+}
+
+// Write an ALTO v4 file per page into dir. Returns the number of pages written
+pub(crate) fn export_alto(state: &State, images: &[String], images_path: &str, dir: &str) -> std::io::Result<usize> {
+    std::fs::create_dir_all(dir)?;
+    let mut count = 0;
+    for filename in images {
+        let Some(page) = state.pages.get(filename) else {
+            continue;
+        };
+        if page.articles.is_empty() {
+            continue;
+        }
+        // Guess the format from the content, in case the extension is wrong
+        let (width, height) = image::ImageReader::open(format!("{}{}", images_path, filename))
+            .and_then(|reader| reader.with_guessed_format())
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok())
+            .unwrap_or((0, 0));
+        let page_rect = Rect::from_min_max(Pos2::ZERO, Pos2::new(width as f32, height as f32));
+
+        let stem = filename.rsplit_once('.').map_or(filename.as_str(), |(stem, _)| stem);
+        let mut out = BufWriter::new(File::create(format!("{}/{}.xml", dir, stem))?);
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(out, "<alto xmlns=\"http://www.loc.gov/standards/alto/ns-v4#\">")?;
+        writeln!(out, "  <Description>")?;
+        writeln!(out, "    <MeasurementUnit>pixel</MeasurementUnit>")?;
+        writeln!(out, "    <sourceImageInformation><fileName>{}</fileName></sourceImageInformation>", xml_escape(filename))?;
+        writeln!(out, "  </Description>")?;
+        writeln!(out, "  <Layout>")?;
+        writeln!(out, "    <Page ID=\"page\" PHYSICAL_IMG_NR=\"1\" WIDTH=\"{}\" HEIGHT=\"{}\">", width, height)?;
+        writeln!(out, "      <PrintSpace {}>", alto_coords(page_rect))?;
+        for article in page.articles.iter().filter(|article| article.kind == ArticleKind::Text && !article.text.starts_with("[NOTE]")) {
+            write_alto_block(&mut out, article, &state.settings.language)?;
+        }
+        writeln!(out, "      </PrintSpace>")?;
+        writeln!(out, "    </Page>")?;
+        writeln!(out, "  </Layout>")?;
+        writeln!(out, "</alto>")?;
+        out.flush()?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+// Standard PDF fonts use WinAnsiEncoding, which is mostly Latin-1 plus some punctuation
+fn win_ansi(c: char) -> u8 {
+    match c {
+        '\u{20}'..='\u{7e}' | '\u{a0}'..='\u{ff}' => c as u8,
+        '€' => 0x80, '‚' => 0x82, '„' => 0x84, '…' => 0x85, '‘' => 0x91, '’' => 0x92,
+        '“' => 0x93, '”' => 0x94, '•' => 0x95, '–' => 0x96, '—' => 0x97,
+        _ => b'?',
+    }
+}
+
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut out = vec![b'('];
+    for c in text.chars() {
+        let b = win_ansi(c);
+        if matches!(b, b'(' | b')' | b'\\') {
+            out.push(b'\\');
+        }
+        out.push(b);
+    }
+    out.push(b')');
+    out
+}
+
+// Content stream operators for some invisible text filling the given rect (in PDF coordinates)
+fn pdf_hidden_text(out: &mut Vec<u8>, text: &str, rect: Rect) {
+    let chars = text.chars().count();
+    if chars == 0 || rect.height() <= 0.0 {
+        return;
+    }
+    let size = rect.height();
+    // Helvetica averages about half an em per character; stretch it to fill the width
+    let stretch = 100.0 * rect.width() / (chars as f32 * size * 0.5);
+    out.extend(format!("BT 3 Tr /F1 {:.2} Tf {:.2} Tz {:.2} {:.2} Td ", size, stretch, rect.left(), rect.top() + size * 0.2).as_bytes());
+    out.extend(pdf_string(text));
+    out.extend(b" Tj ET\n");
+}
+
+// Assumed scan resolution when the project doesn't say, to give the PDF pages a physical size
+const PDF_DPI: f32 = 300.0;
+
+// Writes each object out as soon as it's made, so a long export doesn't keep every page's image
+// in memory, and remembers where they went for the xref table
+struct PdfWriter {
+    out: BufWriter<File>,
+    offset: usize, // bytes written so far
+    offsets: Vec<usize>, // of object n+1, or 0 until it's been written
+}
+
+impl PdfWriter {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.offset += bytes.len();
+        Ok(())
+    }
+
+    // An object ID to write later, for objects that refer to ones not yet made
+    fn reserve(&mut self) -> usize {
+        self.offsets.push(0);
+        self.offsets.len()
+    }
+
+    fn object(&mut self, id: usize, parts: &[&[u8]]) -> std::io::Result<()> {
+        self.offsets[id - 1] = self.offset;
+        self.write(format!("{} 0 obj\n", id).as_bytes())?;
+        for part in parts {
+            self.write(part)?;
+        }
+        self.write(b"\nendobj\n")
+    }
+
+    fn stream(&mut self, id: usize, dict: &str, data: &[u8]) -> std::io::Result<()> {
+        let header = format!("<< {} /Length {} >>\nstream\n", dict, data.len());
+        self.object(id, &[header.as_bytes(), data, b"\nendstream"])
+    }
+}
+
+// Width, height and number of colour components of a baseline or progressive JPEG, from its
+// frame header, or None if it's some other kind that PDF readers mightn't handle
+fn jpeg_frame(bytes: &[u8]) -> Option<(u32, u32, u8)> {
+    if !bytes.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let mut i = 2;
+    loop {
+        let (&0xff, &marker) = (bytes.get(i)?, bytes.get(i + 1)?) else {
+            return None;
+        };
+        if marker == 0xff {
+            i += 1; // padding
+            continue;
+        }
+        let length = u16::from_be_bytes([*bytes.get(i + 2)?, *bytes.get(i + 3)?]) as usize;
+        match marker {
+            0xc0..=0xc2 => {
+                let frame = bytes.get(i + 4..i + 10)?;
+                let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+                let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+                return Some((width, height, frame[5])).filter(|_| width > 0 && height > 0);
+            }
+            0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf | 0xda => return None,
+            _ => i += 2 + length,
+        }
+    }
+}
+
+// A page image as JPEG data for /DCTDecode
+struct PdfImage {
+    jpeg: Vec<u8>,
+    width: u32,
+    height: u32,
+    color_space: &'static str,
+}
+
+// The page image, or None if it can't be loaded. JPEG scans are embedded as they are, since
+// encoding them again loses quality
+fn pdf_image(path: &str) -> std::io::Result<Option<PdfImage>> {
+    if let Ok(jpeg) = std::fs::read(path) {
+        match jpeg_frame(&jpeg) {
+            Some((width, height, 1)) => return Ok(Some(PdfImage { jpeg, width, height, color_space: "/DeviceGray" })),
+            Some((width, height, 3)) => return Ok(Some(PdfImage { jpeg, width, height, color_space: "/DeviceRGB" })),
+            _ => {}
+        }
+    }
+    let Ok((image, _)) = crate::MyApp::decode_image(path) else {
+        return Ok(None);
+    };
+    let mut jpeg = Vec::new();
+    image.write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(&mut std::io::Cursor::new(&mut jpeg), 90))
+        .map_err(std::io::Error::other)?;
+    Ok(Some(PdfImage { jpeg, width: image.width(), height: image.height(), color_space: "/DeviceRGB" }))
+}
+
+// Write a PDF with each page's image, and the article text as an invisible layer over the
+// article regions so it can be searched and copied. Returns the number of pages written
+pub(crate) fn export_pdf(state: &State, images: &[String], images_path: &str, path: &str) -> std::io::Result<usize> {
+    let mut pdf = PdfWriter { out: BufWriter::new(File::create(path)?), offset: 0, offsets: Vec::new() };
+    pdf.write(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n")?;
+    let mut page_ids = Vec::new();
+
+    // The catalog and page tree are written at the end, once the pages are known
+    let catalog_id = pdf.reserve();
+    let pages_id = pdf.reserve();
+    let font_id = pdf.reserve();
+    pdf.object(font_id, &[b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"])?;
+
+    for filename in images {
+        let Some(image) = pdf_image(&format!("{}{}", images_path, filename))? else {
+            continue;
+        };
+        let image_id = pdf.reserve();
+        pdf.stream(image_id, &format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} /BitsPerComponent 8 /Filter /DCTDecode",
+            image.width, image.height, image.color_space
+        ), &image.jpeg)?;
+
+        let (width, height) = (image.width as f32, image.height as f32);
+        let dpi = if state.settings.dpi > 0.0 { state.settings.dpi } else { PDF_DPI };
+        let pt = 72.0 / dpi;
+        // Image-space rect to PDF space, which has y going upwards
+        let to_pdf = |r: Rect| Rect::from_min_max(
+            Pos2::new(r.left() * pt, (height - r.bottom()) * pt),
+            Pos2::new(r.right() * pt, (height - r.top()) * pt),
+        );
+
+        let mut content = format!("q {:.2} 0 0 {:.2} 0 0 cm /Im1 Do Q\n", width * pt, height * pt).into_bytes();
+        for article in state.pages.get(filename).map_or(&[][..], |page| &page.articles) {
+            if article.kind == ArticleKind::Image || article.text.starts_with("[NOTE]") {
+                continue;
+            }
+            let raw_ocr: Option<Vec<_>> = (0..article.polys.len()).map(|i| article.raw_ocr.get(i)?.as_ref()).collect();
+            match raw_ocr {
+                Some(raw_ocr) if !raw_ocr.is_empty() && raw_ocr.iter().all(|ocr| ocr.crop.is_some()) => {
+                    for ocr in raw_ocr {
+                        let crop = ocr.crop.unwrap();
+                        for line in &ocr.lines {
+                            let rect = Rect::from_min_max(
+                                crop.min + line.bbox.min.to_vec2() * crop.size(),
+                                crop.min + line.bbox.max.to_vec2() * crop.size(),
+                            );
+                            pdf_hidden_text(&mut content, &line.text, to_pdf(rect));
+                        }
+                    }
+                }
+                _ => {
+                    // Without line positions, spread the text's lines evenly over the article's region
+                    let Some(bbox) = article.polys.iter().flatten().map(|&p| Rect::from_min_max(p, p)).reduce(Rect::union) else {
+                        continue;
+                    };
+                    let lines: Vec<_> = article.text.lines().map(|line| line.trim_start_matches('#').trim()).filter(|line| !line.is_empty()).collect();
+                    let line_height = bbox.height() / lines.len().max(1) as f32;
+                    for (i, line) in lines.iter().enumerate() {
+                        let top = bbox.top() + i as f32 * line_height;
+                        let rect = Rect::from_min_max(Pos2::new(bbox.left(), top), Pos2::new(bbox.right(), top + line_height));
+                        pdf_hidden_text(&mut content, line, to_pdf(rect));
+                    }
+                }
+            }
+        }
+
+        let content_id = pdf.reserve();
+        pdf.stream(content_id, "", &content)?;
+
+        let page_id = pdf.reserve();
+        pdf.object(page_id, &[format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << /Font << /F1 {} 0 R >> /XObject << /Im1 {} 0 R >> >> /Contents {} 0 R >>",
+            pages_id, width * pt, height * pt, font_id, image_id, content_id
+        ).as_bytes()])?;
+        page_ids.push(page_id);
+    }
+
+    // The language helps screen readers and search with the hidden text
+    let mut catalog = format!("<< /Type /Catalog /Pages {} 0 R", pages_id).into_bytes();
+    if !state.settings.language.is_empty() {
+        catalog.extend(b" /Lang ");
+        catalog.extend(pdf_string(&state.settings.language));
+    }
+    catalog.extend(b" >>");
+    pdf.object(catalog_id, &[&catalog])?;
+    pdf.object(pages_id, &[format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "),
+        page_ids.len()
+    ).as_bytes()])?;
+
+    let PdfWriter { mut out, offset, offsets } = pdf;
+    writeln!(out, "xref\n0 {}\n0000000000 65535 f ", offsets.len() + 1)?;
+    for offset in &offsets {
+        writeln!(out, "{:010} 00000 n ", offset)?;
+    }
+    writeln!(out, "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF", offsets.len() + 1, catalog_id, offset)?;
+    out.flush()?;
+
+    Ok(page_ids.len())
+}
@@ -0,0 +1,252 @@
+// Copyright (c) 2025 Philip Taylor
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// Geometry helpers shared between extraction, hit-testing and analysis
+
+use eframe::epaint::Pos2;
+
+// Test if line (ox, oy)--(inf, oy) intersects (ax, ay)--(bx, by)
+pub(crate) fn ray_intersect(ox: f32, oy: f32, ax: f32, ay: f32, bx: f32, by: f32) -> bool {
+    // Test if a,b on opposite sides of o--inf:
+    if (ay - oy).signum() == (by - oy).signum() {
+        return false;
+    }
+    // Test if o,inf on opposite sides of a--b:
+    //  s0 = (ox-ax, oy-ay) . (by-ay, ax-bx)
+    //  s1 = (ox+inf-ax, oy-ay) . (by-ay, ax-bx) =~ inf*(by-ay)
+    let s0 = ((ox - ax) * (by - ay) + (oy - ay) * (ax - bx)).signum();
+    let s1 = (by - ay).signum();
+    s0 != s1
+}
+
+fn crossings(p: Pos2, ring: &[Pos2]) -> usize {
+    (0..ring.len()).filter(|&i| {
+        let a = ring[i];
+        let b = ring[(i + 1) % ring.len()];
+        ray_intersect(p.x, p.y, a.x, a.y, b.x, b.y)
+    }).count()
+}
+
+// Signed count of the ring's crossings of the ray, with edges going down counting as +1 and up as -1
+fn winding(p: Pos2, ring: &[Pos2]) -> i32 {
+    (0..ring.len()).map(|i| {
+        let a = ring[i];
+        let b = ring[(i + 1) % ring.len()];
+        match (ray_intersect(p.x, p.y, a.x, a.y, b.x, b.y), b.y > a.y) {
+            (false, _) => 0,
+            (true, true) => 1,
+            (true, false) => -1,
+        }
+    }).sum()
+}
+
+// Even-odd test against a single closed ring (the last vertex joins back to the first)
+pub(crate) fn point_in_polygon(p: Pos2, vertexes: &[Pos2]) -> bool {
+    crossings(p, vertexes) % 2 == 1
+}
+
+// Even-odd test against several rings together, so a ring inside another is a hole in it
+pub(crate) fn point_in_rings(p: Pos2, rings: &[Vec<Pos2>]) -> bool {
+    rings.iter().map(|ring| crossings(p, ring)).sum::<usize>() % 2 == 1
+}
+
+// Nonzero winding test against the first ring, so the loops of a self-intersecting outline are
+// filled instead of alternating, with the rest as holes cut out of it whichever way they wind
+pub(crate) fn point_in_rings_nonzero(p: Pos2, rings: &[Vec<Pos2>]) -> bool {
+    let Some((outline, holes)) = rings.split_first() else {
+        return false;
+    };
+    winding(p, outline) != 0 && !holes.iter().any(|hole| winding(p, hole) != 0)
+}
+
+// How much of the pixel at (x, y) is inside, 0-255, from a grid of samples x samples points
+// spread evenly over it
+pub(crate) fn coverage(x: u32, y: u32, samples: u32, inside: impl Fn(Pos2) -> bool) -> u8 {
+    let step = 1.0 / samples as f32;
+    let count = (0..samples * samples).filter(|&i| {
+        let dx = ((i % samples) as f32 + 0.5) * step;
+        let dy = ((i / samples) as f32 + 0.5) * step;
+        inside(Pos2::new(x as f32 + dx, y as f32 + dy))
+    }).count() as u32;
+    ((count * 255 + samples * samples / 2) / (samples * samples)) as u8
+}
+
+// Whether segments a0--a1 and b0--b1 cross at a point inside both of them
+fn segments_cross(a0: Pos2, a1: Pos2, b0: Pos2, b1: Pos2) -> bool {
+    let side = |p: Pos2, q0: Pos2, q1: Pos2| (q1 - q0).x * (p - q0).y - (q1 - q0).y * (p - q0).x;
+    let (d0, d1) = (side(b0, a0, a1), side(b1, a0, a1));
+    let (d2, d3) = (side(a0, b0, b1), side(a1, b0, b1));
+    d0 * d1 < 0.0 && d2 * d3 < 0.0
+}
+
+// Whether any two edges of the closed ring cross, other than neighbours meeting at a vertex
+pub(crate) fn self_intersects(ring: &[Pos2]) -> bool {
+    let n = ring.len();
+    (0..n).any(|i| {
+        // Neighbouring edges share a vertex, including the last with the first
+        (i + 2..n).filter(|&j| !(i == 0 && j == n - 1)).any(|j| {
+            segments_cross(ring[i], ring[(i + 1) % n], ring[j], ring[(j + 1) % n])
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<Pos2> {
+        vec![Pos2::new(x0, y0), Pos2::new(x1, y0), Pos2::new(x1, y1), Pos2::new(x0, y1)]
+    }
+
+    #[test]
+    fn inside_and_outside() {
+        let poly = square(0.0, 0.0, 10.0, 10.0);
+        assert!(point_in_polygon(Pos2::new(5.0, 5.0), &poly));
+        assert!(!point_in_polygon(Pos2::new(15.0, 5.0), &poly));
+        assert!(!point_in_polygon(Pos2::new(-5.0, 5.0), &poly));
+        assert!(!point_in_polygon(Pos2::new(5.0, 15.0), &poly));
+    }
+
+    #[test]
+    fn concave() {
+        // U shape, with the gap at the top between x=3 and x=7
+        let poly = vec![
+            Pos2::new(0.0, 0.0), Pos2::new(3.0, 0.0), Pos2::new(3.0, 7.0), Pos2::new(7.0, 7.0),
+            Pos2::new(7.0, 0.0), Pos2::new(10.0, 0.0), Pos2::new(10.0, 10.0), Pos2::new(0.0, 10.0),
+        ];
+        assert!(point_in_polygon(Pos2::new(1.0, 5.0), &poly));
+        assert!(point_in_polygon(Pos2::new(9.0, 5.0), &poly));
+        assert!(!point_in_polygon(Pos2::new(5.0, 5.0), &poly));
+        assert!(point_in_polygon(Pos2::new(5.0, 8.0), &poly));
+    }
+
+    #[test]
+    fn holes() {
+        let rings = vec![square(0.0, 0.0, 10.0, 10.0), square(3.0, 3.0, 7.0, 7.0)];
+        assert!(point_in_rings(Pos2::new(1.0, 1.0), &rings));
+        assert!(!point_in_rings(Pos2::new(5.0, 5.0), &rings));
+        assert!(!point_in_rings(Pos2::new(15.0, 5.0), &rings));
+
+        // The winding of the hole doesn't matter
+        let mut hole = square(3.0, 3.0, 7.0, 7.0);
+        hole.reverse();
+        let rings = vec![square(0.0, 0.0, 10.0, 10.0), hole];
+        assert!(!point_in_rings(Pos2::new(5.0, 5.0), &rings));
+    }
+
+    #[test]
+    fn ray_through_vertex() {
+        // The ray from (0, 5) passes exactly through the vertexes at (5, 5) and (10, 5)
+        let diamond = vec![Pos2::new(5.0, 0.0), Pos2::new(10.0, 5.0), Pos2::new(5.0, 10.0), Pos2::new(0.0, 5.0)];
+        assert!(point_in_polygon(Pos2::new(4.0, 5.0), &diamond));
+        let arrow = vec![Pos2::new(0.0, 0.0), Pos2::new(10.0, 0.0), Pos2::new(5.0, 5.0), Pos2::new(10.0, 10.0), Pos2::new(0.0, 10.0)];
+        assert!(point_in_polygon(Pos2::new(2.0, 5.0), &arrow));
+        assert!(!point_in_polygon(Pos2::new(8.0, 5.0), &arrow));
+    }
+
+    #[test]
+    fn shared_edges() {
+        // Points on the edges between neighbouring polys belong to exactly one of them,
+        // so nothing is masked twice or missed
+        let squares = [
+            square(0.0, 0.0, 10.0, 10.0),
+            square(10.0, 0.0, 20.0, 10.0),
+            square(0.0, 10.0, 10.0, 20.0),
+            square(10.0, 10.0, 20.0, 20.0),
+        ];
+        for p in [Pos2::new(10.0, 5.0), Pos2::new(5.0, 10.0), Pos2::new(10.0, 15.0), Pos2::new(15.0, 10.0)] {
+            let count = squares.iter().filter(|poly| point_in_polygon(p, poly)).count();
+            assert_eq!(count, 1, "{:?}", p);
+        }
+    }
+
+    // A figure eight, whose loops wind in opposite directions, and a pentagram, whose centre is
+    // wound twice
+    #[test]
+    fn nonzero() {
+        let eight = [vec![Pos2::new(0.0, 0.0), Pos2::new(10.0, 10.0), Pos2::new(10.0, 0.0), Pos2::new(0.0, 10.0)]];
+        for p in [Pos2::new(2.0, 5.0), Pos2::new(8.0, 5.0)] {
+            assert!(point_in_polygon(p, &eight[0]));
+            assert!(point_in_rings_nonzero(p, &eight));
+        }
+        assert!(!point_in_rings_nonzero(Pos2::new(5.0, 2.0), &eight));
+
+        let star = [vec![Pos2::new(5.0, 0.0), Pos2::new(8.0, 10.0), Pos2::new(0.0, 4.0), Pos2::new(10.0, 4.0), Pos2::new(2.0, 10.0)]];
+        let centre = Pos2::new(5.0, 5.0);
+        assert!(!point_in_polygon(centre, &star[0]));
+        assert!(point_in_rings_nonzero(centre, &star));
+        assert!(!point_in_rings_nonzero(Pos2::new(0.0, 9.0), &star));
+    }
+
+    #[test]
+    fn nonzero_holes() {
+        // Holes are cut out whichever way they wind, even though they'd cancel out or add up otherwise
+        let outline = square(0.0, 0.0, 10.0, 10.0);
+        let mut reversed = square(3.0, 3.0, 7.0, 7.0);
+        reversed.reverse();
+        for hole in [square(3.0, 3.0, 7.0, 7.0), reversed] {
+            let rings = vec![outline.clone(), hole];
+            assert!(point_in_rings_nonzero(Pos2::new(1.0, 1.0), &rings));
+            assert!(!point_in_rings_nonzero(Pos2::new(5.0, 5.0), &rings));
+        }
+    }
+
+    #[test]
+    fn self_intersection() {
+        assert!(!self_intersects(&square(0.0, 0.0, 10.0, 10.0)));
+        assert!(self_intersects(&[Pos2::new(0.0, 0.0), Pos2::new(10.0, 10.0), Pos2::new(10.0, 0.0), Pos2::new(0.0, 10.0)]));
+        // Concave, and touching at a vertex without crossing
+        assert!(!self_intersects(&[
+            Pos2::new(0.0, 0.0), Pos2::new(3.0, 0.0), Pos2::new(3.0, 7.0), Pos2::new(7.0, 7.0),
+            Pos2::new(7.0, 0.0), Pos2::new(10.0, 0.0), Pos2::new(10.0, 10.0), Pos2::new(0.0, 10.0),
+        ]));
+        assert!(!self_intersects(&[Pos2::new(0.0, 0.0), Pos2::new(10.0, 0.0)]));
+        assert!(!self_intersects(&[]));
+    }
+
+    #[test]
+    fn antialiased_edges() {
+        // A vertical edge halfway across a pixel covers half of it, where a single sample would say
+        // all or nothing
+        let rect = square(0.0, 0.0, 2.5, 4.0);
+        let inside = |p| point_in_polygon(p, &rect);
+        assert_eq!(coverage(1, 1, 4, inside), 255);
+        assert_eq!(coverage(2, 1, 4, inside), 128);
+        assert_eq!(coverage(3, 1, 4, inside), 0);
+
+        // Along a diagonal edge, each row's coverage adds up to about the area of the row that's
+        // inside, and more closely than counting the pixels whose corner is inside
+        let triangle = [Pos2::new(0.0, 0.0), Pos2::new(15.9, 0.0), Pos2::new(0.0, 15.9)];
+        let inside = |p| point_in_polygon(p, &triangle);
+        for y in 0..15 {
+            let area = 15.4 - y as f32;
+            let smooth: f32 = (0..16).map(|x| coverage(x, y, 4, inside) as f32 / 255.0).sum();
+            let hard = (0..16).filter(|&x| inside(Pos2::new(x as f32, y as f32))).count() as f32;
+            assert!((smooth - area).abs() < 0.1, "row {}: {} vs {}", y, smooth, area);
+            assert!((smooth - area).abs() < (hard - area).abs(), "row {}: {} vs {}", y, smooth, hard);
+        }
+    }
+
+    #[test]
+    fn degenerate() {
+        assert!(!point_in_polygon(Pos2::new(0.0, 0.0), &[]));
+        assert!(!point_in_polygon(Pos2::new(1.0, 0.0), &[Pos2::new(0.0, 0.0), Pos2::new(2.0, 0.0)]));
+    }
+}
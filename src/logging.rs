@@ -0,0 +1,91 @@
+// Copyright (c) 2025 Philip Taylor
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// A logger that keeps the recent records for the log panel, as well as passing them on to
+// env_logger, so problems can be seen without running from a terminal
+
+use std::{collections::VecDeque, sync::Mutex};
+
+// How many records the log panel keeps
+const LOG_LENGTH: usize = 500;
+
+#[derive(Clone)]
+pub(crate) struct Record {
+    pub(crate) level: log::Level,
+    pub(crate) time: u64, // seconds since the Unix epoch
+    pub(crate) message: String,
+}
+
+static RECORDS: Mutex<VecDeque<Record>> = Mutex::new(VecDeque::new());
+
+struct Logger {
+    inner: env_logger::Logger,
+}
+
+impl Logger {
+    // Everything from this crate, but only warnings from dependencies like the AWS SDK,
+    // which are very chatty at info
+    fn keep(metadata: &log::Metadata) -> bool {
+        if metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            metadata.level() <= log::Level::Info
+        } else {
+            metadata.level() <= log::Level::Warn
+        }
+    }
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        Self::keep(metadata) || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+        if Self::keep(record.metadata()) {
+            let mut records = RECORDS.lock().unwrap();
+            if records.len() == LOG_LENGTH {
+                records.pop_front();
+            }
+            records.push_back(Record { level: record.level(), time: crate::unix_time(), message: record.args().to_string() });
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+// Install the logger, with env_logger's filtering still configured by RUST_LOG
+pub(crate) fn init() {
+    let inner = env_logger::Builder::from_default_env().build();
+    log::set_max_level(inner.filter().max(log::LevelFilter::Info));
+    log::set_boxed_logger(Box::new(Logger { inner })).unwrap();
+}
+
+// A copy of the records, so the lock isn't held while they're drawn, when logging would deadlock
+pub(crate) fn records() -> Vec<Record> {
+    RECORDS.lock().unwrap().iter().cloned().collect()
+}
+
+pub(crate) fn clear() {
+    RECORDS.lock().unwrap().clear();
+}
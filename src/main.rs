@@ -18,7 +18,11 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use std::{fs::File, collections::BTreeMap, cmp::Ordering, io::Cursor};
+// egui_extras::RetainedImage is deprecated but still the simplest way to hold
+// a texture that's created before we have an egui::Context
+#![allow(deprecated)]
+
+use std::{fs::File, collections::{BTreeMap, BTreeSet, HashMap}, cmp::Ordering, io::Cursor};
 
 use eframe::{
     egui::{self, Sense},
@@ -29,8 +33,28 @@ use egui_extras::RetainedImage;
 use image::RgbImage;
 use serde::{Deserialize, Serialize};
 
+mod export;
+mod geometry;
+mod logging;
+mod schema;
+
 fn main() -> Result<(), eframe::Error> {
-    env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+    logging::init(); // Log to the log panel, and to stderr (if you run with `RUST_LOG=debug`).
+
+    let readonly = std::env::args().skip(1).any(|arg| arg == "--readonly");
+
+    if std::env::args().skip(1).any(|arg| arg == "--batch") {
+        let mut app = MyApp::default();
+        if std::env::args().skip(1).any(|arg| arg == "--dry-run") {
+            for line in app.estimate_batch().summary() {
+                println!("{}", line);
+            }
+        } else {
+            app.run_batch();
+        }
+        return Ok(());
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_maximized(true),
         ..Default::default()
@@ -38,23 +62,134 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "Annotator",
         options,
-        Box::new(|_cc| Ok(Box::<MyApp>::default())),
+        Box::new(move |cc| {
+            let mut app = Box::<MyApp>::default();
+            app.readonly = readonly;
+            cc.egui_ctx.set_pixels_per_point(app.projects.pixels_per_point);
+            Ok(app)
+        }),
     )
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[allow(dead_code)]
 struct Line {
     text: String,
     points: Vec<Vec2>,
     bbox: Rect,
     left: f32,
     mid: Vec2,
+    #[serde(default)]
+    confidence: f32, // percent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    paragraph: Option<bool>, // manual override of whether a paragraph starts here
 }
 
-#[derive(Serialize, Deserialize)]
+// The OCR output a poly's text was merged from, so it can be merged again later
+#[derive(Serialize, Deserialize, Clone)]
+struct RawOcr {
+    width: f32, // of the crop, in pixels
+    #[serde(default)]
+    crop: Option<Rect>, // image-space region the line coordinates are relative to
+    paragraph: bool, // appended with Append P
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    separator: Option<String>, // appended with a custom separator instead of a new line
+    lines: Vec<Line>,
+}
+
+// How a poly's text was produced, to tell a stale backend or bad settings from a hard scan
+#[derive(Serialize, Deserialize, Clone)]
+struct Provenance {
+    backend: String, // e.g. "textract"
+    settings: String, // summary of whatever affected the crop and the merge
+    at: u64, // seconds since the Unix epoch
+}
+
+// How appended text is joined onto the article's existing text
+#[derive(Clone)]
+enum Join {
+    Line,
+    Paragraph,
+    Custom(String), // in place of the new line at the end of the existing text
+}
+
+impl Join {
+    fn apply(&self, text: &mut String) {
+        match self {
+            Join::Line => {}
+            Join::Paragraph => text.push('\n'),
+            Join::Custom(separator) => {
+                if text.ends_with('\n') {
+                    text.pop();
+                }
+                text.push_str(separator);
+            }
+        }
+    }
+}
+
+impl RawOcr {
+    fn join(&self) -> Join {
+        match (&self.separator, self.paragraph) {
+            (Some(separator), _) => Join::Custom(separator.clone()),
+            (None, true) => Join::Paragraph,
+            (None, false) => Join::Line,
+        }
+    }
+
+    // None for files saved before confidence was recorded
+    fn mean_confidence(&self) -> Option<f32> {
+        if self.lines.is_empty() || self.lines.iter().all(|line| line.confidence == 0.0) {
+            return None;
+        }
+        Some(self.lines.iter().map(|line| line.confidence).sum::<f32>() / self.lines.len() as f32)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct Article {
+    #[serde(default)]
+    id: u64, // unique within the State; 0 until assigned
     polys: Vec<Vec<Pos2>>,
     text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    raw_ocr: Vec<Option<RawOcr>>, // parallel to polys, or empty in older files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    holes: Vec<Vec<Vec<Pos2>>>, // rings masked out of each poly; parallel to polys, or empty if there are none
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    provenance: Vec<Option<Provenance>>, // parallel to polys, or empty if none was recorded
+    #[serde(default, skip_serializing_if = "is_false")]
+    verified: bool, // proofread, or confident enough not to need it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>, // where the clipping came from, e.g. "The Times, evening edition"
+    #[serde(default, skip_serializing_if = "ArticleKind::is_text")]
+    kind: ArticleKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    caption: Option<String>, // of an image article, which has no text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    crop: Option<String>, // path of a PNG of an image article's region, if one was saved when it was added
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_at: Option<u64>, // seconds since the Unix epoch, or None in older files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified_at: Option<u64>,
+}
+
+// Most clippings are text to transcribe, but some are photos or illustrations that OCR can't help with
+#[derive(Clone, Copy, PartialEq, Default, Debug, Serialize, Deserialize)]
+enum ArticleKind {
+    #[default]
+    Text,
+    Image,
+}
+
+impl ArticleKind {
+    fn is_text(&self) -> bool {
+        *self == ArticleKind::Text
+    }
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
 }
 
 #[derive(Serialize, Deserialize)]
@@ -62,469 +197,4556 @@ struct Page {
     date: Option<String>,
     summary: Option<String>,
     articles: Vec<Article>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    notes: String, // about the page itself, not part of any article
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    open_article: Option<u64>, // Article::id, restored when returning to the page
+    #[serde(default, skip_serializing_if = "is_false")]
+    blank: bool, // nothing to annotate, e.g. the back of a page
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>, // categories for the whole page, e.g. "sports"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    scratchpad: Vec<Scrap>, // extractions collected before deciding which articles they belong to
+}
+
+// An extraction put aside on the scratchpad, with everything needed to append it to an article later
+#[derive(Clone, Serialize, Deserialize)]
+struct Scrap {
+    text: String,
+    vertexes: Vec<Pos2>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    holes: Vec<Vec<Pos2>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    raw_ocr: Option<RawOcr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    provenance: Option<Provenance>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+enum ImageOrder {
+    Lexical,
+    Natural, // "page2" before "page10"
+    Modified, // file modification time
+}
+
+// A named combination of the preprocessing applied to a crop before OCR, to compare them quickly
+#[derive(Serialize, Deserialize, Clone)]
+struct Preset {
+    name: String,
+    levels: (u8, u8), // black and white points
+    mask_fill: [u8; 3],
+}
+
+impl Preset {
+    fn defaults() -> Vec<Self> {
+        let preset = |name: &str, levels, mask_fill| Preset { name: name.to_owned(), levels, mask_fill };
+        vec![
+            preset("Original", (0, 255), [48, 48, 48]),
+            preset("High contrast", (60, 200), [48, 48, 48]),
+            preset("Faded ink", (0, 160), [48, 48, 48]),
+            preset("Dark paper", (80, 255), [48, 48, 48]),
+            preset("White mask", (0, 255), [255, 255, 255]),
+        ]
+    }
+}
+
+// Per-collection settings, saved in the annotations file
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    simplify_polys: bool,
+    simplify_tolerance: f32, // image-space pixels
+    image_order: ImageOrder,
+    mask_fill: [u8; 3], // colour outside the poly in the extracted image
+    store_raw_ocr: bool,
+    auto_verify: bool,
+    auto_verify_confidence: f32, // percent
+    guard_clear: bool, // need Ctrl+click to start a new poly when the current one has more than 3 points
+    line_sort_fudge: bool, // order lines at about the same height by x, as well as by y
+    line_sort_divisor: f32, // how much x counts relative to y, in the fudge
+    right_to_left: bool, // the fudge orders lines at the same height from the right
+    row_clustering: bool, // group lines into rows by vertical position instead of using the fudge
+    row_threshold: f32, // how far apart lines' centres can be in the same row, as a fraction of the median line height
+    join_split_lines: bool, // join lines that Textract split side by side
+    cross_fade: bool, // between images when navigating, instead of swapping instantly
+    confidence_tint: bool, // colour poly outlines by their OCR confidence
+    vertex_radius: f32, // of the handles on the poly being drawn
+    vertex_radius_image_space: bool, // vertex_radius is in image pixels rather than screen units
+    hide_chrome_key: String, // egui::Key name that toggles the sidebar and popup
+    integer_polys: bool, // round poly coordinates to whole pixels when saving
+    language: String, // BCP 47 tag for exports, e.g. "en-GB", or empty if unknown
+    paragraph_indent_min: f32, // image-space pixels a line must be indented by to start a paragraph
+    paragraph_indent_max: f32, // and beyond which it's probably a different column instead
+    paragraph_short_line: f32, // fraction of the median line length below which a line ending a sentence ends a paragraph, when splitting the draft
+    dpi: f32, // resolution the pages were scanned at, for the ruler, or 0 if unknown
+    nonzero_fill: bool, // mask self-intersecting polys by winding, so loops are filled instead of alternating
+    save_image_crops: bool, // write a PNG of each image article's region when it's added
+    antialias_mask: bool, // blend the mask's edge pixels by how much of them is inside, instead of all or nothing
+    preview_closing_edge: bool, // show the edge back to the start faintly while a poly is being drawn
+    presets: Vec<Preset>, // cycled through with P
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            simplify_polys: false,
+            simplify_tolerance: 2.0,
+            image_order: ImageOrder::Natural,
+            mask_fill: [48, 48, 48],
+            store_raw_ocr: true,
+            auto_verify: false,
+            auto_verify_confidence: 99.0,
+            guard_clear: false,
+            line_sort_fudge: true,
+            line_sort_divisor: 40.0,
+            right_to_left: false,
+            row_clustering: false,
+            row_threshold: 0.5,
+            join_split_lines: true,
+            cross_fade: true,
+            confidence_tint: false,
+            vertex_radius: 3.0,
+            vertex_radius_image_space: false,
+            hide_chrome_key: "F2".to_owned(),
+            integer_polys: false,
+            language: String::new(),
+            paragraph_indent_min: 8.0,
+            paragraph_indent_max: 40.0,
+            paragraph_short_line: 0.8,
+            dpi: 0.0,
+            nonzero_fill: false,
+            save_image_crops: false,
+            antialias_mask: false,
+            preview_closing_edge: true,
+            presets: Preset::defaults(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 struct State {
     images: Vec<String>,
+    #[serde(serialize_with = "serialize_pages")]
     pages: BTreeMap<String, Page>,
     open_image: usize,
+    #[serde(default)]
+    settings: Settings,
+    #[serde(skip)]
+    next_article_id: u64,
+}
+
+// What a batch extraction would do, worked out without loading any images or calling OCR
+struct BatchEstimate {
+    pages: usize,
+    articles: usize,
+    calls: usize, // regions that would be sent for OCR
+    skipped: Vec<String>, // regions that would be skipped, and why
+}
+
+impl BatchEstimate {
+    fn summary(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Would extract {} articles on {} pages, with {} OCR calls costing about ${:.2}",
+            self.articles, self.pages, self.calls, self.calls as f64 * OCR_COST,
+        )];
+        if !self.skipped.is_empty() {
+            lines.push(format!("Would skip {} problem regions:", self.skipped.len()));
+            lines.extend(self.skipped.iter().map(|problem| format!("  {}", problem)));
+        }
+        lines
+    }
+}
+
+// Totals over the whole project, to show how far the annotation has got
+struct Statistics {
+    pages: usize, // images in the project
+    annotated: usize, // pages with at least one article
+    blank: usize,
+    articles: usize,
+    verified: usize,
+    words: usize,
+    articles_per_page: BTreeMap<usize, usize>, // number of articles, to how many non-blank pages have that many
+    dates: Option<(String, String)>, // earliest and latest of the dates that start with a year
+    undated: usize, // non-blank pages with no date, or one that doesn't start with a year
+    ocr_calls: usize, // polys and scraps whose text came from OCR
+}
+
+impl Statistics {
+    fn summary(&self) -> Vec<String> {
+        let percent = |n: usize, total: usize| if total == 0 { 0.0 } else { n as f32 * 100.0 / total as f32 };
+        let mut lines = vec![
+            format!("{} pages: {} annotated and {} blank ({:.0}% done)", self.pages, self.annotated, self.blank, percent(self.annotated + self.blank, self.pages)),
+            format!("{} articles, {} verified ({:.0}%)", self.articles, self.verified, percent(self.verified, self.articles)),
+            format!("{} words", self.words),
+            format!("{} OCR calls, costing about ${:.2}", self.ocr_calls, self.ocr_calls as f64 * OCR_COST),
+        ];
+        match &self.dates {
+            Some((first, last)) => lines.push(format!("Dates from {} to {}, with {} pages undated", first, last, self.undated)),
+            None => lines.push(format!("No dates yet, with {} pages undated", self.undated)),
+        }
+        lines.push("Articles per page:".to_owned());
+        let most = self.articles_per_page.values().copied().max().unwrap_or(0);
+        for (articles, pages) in &self.articles_per_page {
+            // A bar scaled to the commonest count, so the shape of the distribution shows at a glance
+            let bar = "█".repeat((pages * 20).div_ceil(most.max(1)));
+            lines.push(format!("  {:>3}: {:>4} pages {}", articles, pages, bar));
+        }
+        lines
+    }
+}
+
+// A pair of articles on the current page that look like the same clipping, by id so edits made
+// while the window is open can't point it at the wrong articles
+struct Duplicate {
+    a: u64,
+    b: u64,
+    text_similarity: f32,
+    overlap: f32,
+}
+
+// What a bulk action did, shown until it's dismissed, with a way to undo all of it at once
+struct BatchSummary {
+    title: &'static str,
+    pages: usize,
+    changed: usize,
+    problems: Vec<String>, // items that were skipped, and why
+    undo: BatchUndo,
+}
+
+// Detect blanks, which decodes every unannotated page, so it runs on another thread
+struct BlankDetection {
+    receiver: std::sync::mpsc::Receiver<(String, Result<bool, String>)>, // whether each page is blank, as it's checked
+    pages: usize, // how many will be checked
+    checked: usize,
+    blank: Vec<String>,
+    problems: Vec<String>,
+}
+
+enum BatchUndo {
+    TextChanges(Vec<TextChange>),
+    Blanks(Vec<String>), // filenames of the pages that were marked blank
+}
+
+// A proposed change to an article's text, from re-merging its stored OCR or from a Transform
+#[derive(Clone)]
+struct TextChange {
+    page: String,
+    article: u64,
+    old: String,
+    new: String,
+    apply: bool,
+}
+
+// Cleanups that can be applied to many articles at once
+#[derive(Clone, Copy, PartialEq)]
+enum Transform {
+    CollapseWhitespace,
+    StripBrackets,
+    UppercaseHeadings,
+    StraightenQuotes,
+    FixCharacters,
+}
+
+impl Transform {
+    const ALL: [Transform; 5] = [Transform::CollapseWhitespace, Transform::StripBrackets, Transform::UppercaseHeadings, Transform::StraightenQuotes, Transform::FixCharacters];
+
+    fn name(self) -> &'static str {
+        match self {
+            Transform::CollapseWhitespace => "Collapse whitespace",
+            Transform::StripBrackets => "Strip [bracketed notes]",
+            Transform::UppercaseHeadings => "Uppercase # headings",
+            Transform::StraightenQuotes => "Straighten quotes",
+            Transform::FixCharacters => "Fix unusual characters",
+        }
+    }
+
+    fn apply(self, text: &str) -> String {
+        match self {
+            Transform::CollapseWhitespace => {
+                // Single spaces within lines, and no more than one blank line in a row
+                let mut out = String::new();
+                let mut blank = false;
+                for line in text.lines() {
+                    let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+                    if line.is_empty() && (blank || out.is_empty()) {
+                        continue;
+                    }
+                    blank = line.is_empty();
+                    out += &line;
+                    out.push('\n');
+                }
+                if !text.ends_with('\n') {
+                    out.pop();
+                }
+                out
+            }
+            Transform::StripBrackets => {
+                // Keep the [NOTE] marker, since that's not an editorial note but says what kind of article it is
+                let (marker, rest) = match text.strip_prefix("[NOTE]") {
+                    Some(rest) => ("[NOTE]", rest),
+                    None => ("", text),
+                };
+                let mut out = String::from(marker);
+                let mut depth = 0;
+                for c in rest.chars() {
+                    match c {
+                        '[' => depth += 1,
+                        ']' if depth > 0 => depth -= 1,
+                        _ if depth == 0 => out.push(c),
+                        _ => {}
+                    }
+                }
+                out
+            }
+            Transform::UppercaseHeadings => {
+                let mut out: String = text.lines()
+                    .map(|line| if line.starts_with('#') { line.to_uppercase() } else { line.to_owned() } + "\n")
+                    .collect();
+                if !text.ends_with('\n') {
+                    out.pop();
+                }
+                out
+            }
+            Transform::StraightenQuotes => text.replace(['‘', '’'], "'").replace(['“', '”'], "\""),
+            Transform::FixCharacters => text.chars().fold(String::new(), |mut out, c| {
+                match char_replacement(c) {
+                    Some((_, replacement)) => out += replacement,
+                    None => out.push(c),
+                }
+                out
+            }),
+        }
+    }
+}
+
+// What's wrong with a character OCR sometimes produces that's invisible or breaks other tools,
+// and what to replace it with (possibly nothing), or None if it's fine
+fn char_replacement(c: char) -> Option<(&'static str, &'static str)> {
+    Some(match c {
+        '\n' | '\t' => return None,
+        '\u{fffd}' => ("replacement character", ""),
+        '\u{200b}' | '\u{200c}' | '\u{200d}' | '\u{2060}' | '\u{feff}' => ("zero-width character", ""),
+        '\u{00ad}' => ("soft hyphen", ""),
+        '\u{00a0}' | '\u{2000}'..='\u{200a}' | '\u{202f}' | '\u{205f}' | '\u{3000}' => ("unusual space", " "),
+        '\u{2028}' | '\u{2029}' => ("line separator", "\n"),
+        '\u{fb00}' => ("ligature", "ff"),
+        '\u{fb01}' => ("ligature", "fi"),
+        '\u{fb02}' => ("ligature", "fl"),
+        '\u{fb03}' => ("ligature", "ffi"),
+        '\u{fb04}' => ("ligature", "ffl"),
+        '\u{e000}'..='\u{f8ff}' => ("private use character", ""),
+        _ if c.is_control() => ("control character", ""),
+        _ => return None,
+    })
+}
+
+// An unusual character in an article, from char_replacement
+struct Gremlin {
+    page: String,
+    article: u64,
+    line: usize, // from 1, as in the cursor readout
+    column: usize, // from 1, in characters
+    c: char,
+    problem: &'static str,
+    replacement: &'static str,
+}
+
+// An earlier OCR result, kept in memory for the session
+struct Extraction {
+    page: String,
+    vertexes: Vec<Pos2>,
+    holes: Vec<Vec<Pos2>>,
+    crop_image: RgbImage,
+    crop_rect: Rect,
+    crop_mask: Vec<u8>,
+    histogram: Vec<u32>,
+    retained_crop: RetainedImage,
+    text: String,
+    lines: Vec<Line>,
+    provenance: Option<Provenance>,
+}
+
+// A poly's shape, copied to paste onto another page or article. It goes on the system
+// clipboard as JSON too, so it can be kept in a file or pasted into another instance
+#[derive(Serialize, Deserialize, Clone)]
+struct CopiedPoly {
+    vertexes: Vec<Pos2>, // image-space coords
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    holes: Vec<Vec<Pos2>>,
+}
+
+// An article's text handed over to an external editor, read back whenever the file is saved
+struct ExternalEdit {
+    page: String,
+    article: u64, // Article::id
+    path: std::path::PathBuf,
+    child: std::process::Child,
+    text: String, // as last written or read back, to tell whether it's since been changed in the app
+    modified: Option<std::time::SystemTime>, // of the file when it was last read
+}
+
+impl ExternalEdit {
+    // Stop following the editor, leaving its file where it is, but still wait for it to exit so
+    // it isn't left as a zombie
+    fn abandon(mut self) {
+        std::thread::spawn(move || self.child.wait());
+    }
+}
+
+// A page's articles and scratchpad from before an edit, to undo it
+struct Snapshot {
+    articles: Vec<Article>,
+    scratchpad: Vec<Scrap>,
+}
+
+// Ways of leaving the open annotations, which all check for unsaved changes first
+#[derive(Clone, Copy)]
+enum Leave {
+    Project(usize),
+    Quit,
 }
 
 struct MyApp {
     runtime: tokio::runtime::Runtime,
 
+    projects: Projects,
+    project: Project, // the open one
+    leaving: Option<(Leave, bool)>, // waiting for the user to decide whether to save first, and whether there were unsaved changes
+    quitting: bool, // the user has agreed to quit, so don't stop the window closing again
+    saved: String, // the annotations as last loaded or saved, to tell if there are unsaved changes
+    new_project: Project, // being filled in by the user
+
     image: RgbImage,
     retained_image: RetainedImage,
+    fade_from: Option<(RetainedImage, f64)>, // the previous image, and when we started fading it out
+    images_problem: Option<String>, // why the images folder doesn't look right, from check_images
+    images_path_edit: String, // being edited to fix images_problem
+    loading: Option<std::sync::mpsc::Receiver<Result<(RgbImage, image::ImageFormat), String>>>, // the open image being decoded in the background
 
     crop_image: RgbImage,
+    crop_rect: Rect, // image-space coords of crop_image
     retained_crop: RetainedImage,
+    retained_source_crop: RetainedImage, // the same region without the mask
+    crop_outline: bool, // preview the source with the poly drawn on it, instead of the masked crop
+    crop_mask: Vec<u8>, // how much of each pixel of crop_rect is inside the poly, 0-255
+    histogram: Vec<u32>, // luma histogram of the pixels inside the poly
+    levels: (u8, u8), // black and white points applied to the crop before OCR
+    preset_fill: Option<[u8; 3]>, // mask fill of the preset being tried, instead of the saved setting
+    new_preset: String, // name being typed for saving the current preprocessing as a preset
 
     vertexes: Vec<Pos2>, // image-space coords
+    holes: Vec<Vec<Pos2>>, // rings to mask out of vertexes
+    drawing: bool, // vertexes are still being added, so the edge back to the start is provisional
+    previous_vertexes: Option<(Vec<Pos2>, Vec<Vec<Pos2>>)>, // the poly and holes before the current one was started
     lines: Vec<Line>,
+    provenance: Option<Provenance>, // of the lines, from when they were extracted
+    jpeg_size: usize, // of the last crop sent for OCR, to size the next one's buffer
     draft_text: String,
+    draft_appended: bool,
+    draft_cursor: (usize, usize), // char indexes of the draft's selection when it last had focus, for #
+    separator: String, // for Append with
+    history: std::collections::VecDeque<Extraction>, // most recent first
+    show_history: bool,
+    scrap_selection: BTreeSet<usize>, // indexes into the open page's scratchpad
+    copied_poly: Option<CopiedPoly>, // the last poly copied with Ctrl+C or an article's Copy button
+    scale: f32, // screen-space units per image-space pixel
+    pending_navigation: Option<usize>, // image we'll go to once the user decides what to do with the draft
+    status: String,
     offset: Vec2,
 
     state: State,
-    open_article: Option<usize>,
+    open_article: Option<u64>, // Article::id
+    selected_poly: Option<(u64, usize)>, // article id and poly index, chosen with the arrow keys in the open article's poly list
+    duplicates: Option<Vec<Duplicate>>,
+    out_of_bounds: Vec<(u64, usize)>, // (article id, poly index) with points outside the image
+    text_changes: Option<(&'static str, Vec<TextChange>)>, // with the title of the window that previews them
+    last_text_changes: Vec<TextChange>, // the most recently applied, for undo
+    gremlins: Option<Vec<Gremlin>>, // unusual characters found by Check characters, until the window is closed
+    undo: HashMap<String, Vec<Snapshot>>, // earlier versions of each page, most recent last
+    editing_text: Option<Article>, // the article whose text or source has focus in the sidebar, as it was when it got focus
+    external_edit: Option<ExternalEdit>,
+    transform: Transform,
+    transform_pages: (usize, usize), // first and last image index to transform
+    transform_open_article: bool, // only transform the open article
+    readonly: bool, // disables everything that would modify the annotations
+    show_boxes: bool,
+    article_heights: HashMap<u64, f32>, // height of each article in the sidebar when last drawn
+    likely_blank: bool, // the open image looks blank, from is_blank
+    skip_blanks: bool, // < and > skip pages marked as blank
+    tag_filter: Option<String>, // < > and exports only include pages with this tag
+    insert_image: String, // filename being typed into the images list editor
+    page_input: Option<String>, // page index field contents, while they aren't a valid index
+    batch_summary: Option<BatchSummary>, // results of the last bulk action, until dismissed
+    blank_detection: Option<BlankDetection>, // running in the background, from Detect blanks
+    batch_estimate: Option<BatchEstimate>, // from the last dry run
+    statistics: Option<Statistics>, // computed when the Statistics header is opened, or refreshed
+    choosing_append_target: bool, // the next click on an article's poly or header appends the draft to it
+    draft_page: Option<String>, // image the draft was extracted from, or None if it was typed
+    selection_highlight: Vec<Rect>, // image-space boxes around the text selected in an editor, from the last frame
+    fast_entry: bool, // drag a box to extract, and edit the draft in a full-width panel instead of the popup
+    box_start: Option<Pos2>, // image-space corner of the box being dragged in fast entry mode
+    new_tag: String, // being typed into the tag editor
+    show_minimap: bool,
+    show_ruler: bool, // a scale bar in physical units, or pixels if the DPI isn't known
+    hide_chrome: bool, // hide the sidebar, popup and minimap
+    measuring: bool, // clicks measure distances instead of drawing polys
+    measure: Vec<Pos2>, // image-space endpoints of the measurement, up to 2
+    recent_first: bool, // list the articles by modification time instead of page order
+    export_pages: (usize, usize), // first and last image index to export
+}
+
+// One scrapbook: an annotations file plus the directory of images it refers to
+#[derive(Serialize, Deserialize, Clone)]
+struct Project {
+    name: String,
+    annotations: String,
+    images: String, // including the trailing slash
+    scale: f32, // initial zoom
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    layout: Option<Layout>,
 }
 
-// const ANNOTATIONS_FILENAME: &str = "annotations/annotations.yaml";
-// const JPEG_PATH: &str = "../scrapbook-images/jpeg1/pages/";
-// const DEFAULT_SCALE: f32 = 0.75;
+// The layout-specific settings of a project's collection, which override whatever is in
+// its annotations file whenever it's opened
+#[derive(Serialize, Deserialize, Clone)]
+struct Layout {
+    language: String,
+    right_to_left: bool,
+    mask_fill: [u8; 3],
+    paragraph_indent_min: f32,
+    paragraph_indent_max: f32,
+}
 
-// const ANNOTATIONS_FILENAME: &str = "annotations/annotations2.yaml";
-// const JPEG_PATH: &str = "../scrapbook-images/jpeg2/";
-// const DEFAULT_SCALE: f32 = 0.5;
+impl Layout {
+    fn from_settings(settings: &Settings) -> Self {
+        Self {
+            language: settings.language.clone(),
+            right_to_left: settings.right_to_left,
+            mask_fill: settings.mask_fill,
+            paragraph_indent_min: settings.paragraph_indent_min,
+            paragraph_indent_max: settings.paragraph_indent_max,
+        }
+    }
 
-const ANNOTATIONS_FILENAME: &str = "annotations/annotations3.yaml";
-const JPEG_PATH: &str = "../scrapbook-images/jpeg3/";
-const DEFAULT_SCALE: f32 = 0.125;
+    fn apply(&self, settings: &mut Settings) {
+        settings.language = self.language.clone();
+        settings.right_to_left = self.right_to_left;
+        settings.mask_fill = self.mask_fill;
+        settings.paragraph_indent_min = self.paragraph_indent_min;
+        settings.paragraph_indent_max = self.paragraph_indent_max;
+    }
+}
 
-impl Default for MyApp {
+impl Project {
+    fn load_state(&self) -> State {
+        let mut state = State::load(&self.annotations);
+        if let Some(layout) = &self.layout {
+            layout.apply(&mut state.settings);
+        }
+        state
+    }
+
+    // Exported files are written next to the annotations file, with a suffix
+    fn export_path(&self, suffix: &str) -> String {
+        format!("{}{}", self.annotations.trim_end_matches(".yaml"), suffix)
+    }
+}
+
+// Which OCR service extracts the text. Each has its own settings, kept while another is chosen
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+enum OcrBackend {
+    #[default]
+    Textract,
+}
+
+impl OcrBackend {
+    const ALL: [OcrBackend; 1] = [OcrBackend::Textract];
+
+    // Recorded in each poly's provenance
+    fn name(self) -> &'static str {
+        match self {
+            OcrBackend::Textract => "textract",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            OcrBackend::Textract => "AWS Textract",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct TextractSettings {
+    region: String, // or empty for the AWS default (AWS_REGION or the profile's region)
+    profile: String, // named profile in the AWS credentials file, or empty for the default chain
+}
+
+impl Default for TextractSettings {
     fn default() -> Self {
-        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        Self { region: "eu-west-2".to_owned(), profile: String::new() }
+    }
+}
 
-        let mut state;
-        if let Ok(file) = File::open(ANNOTATIONS_FILENAME) {
-            state = serde_yaml::from_reader(file).unwrap();
-        } else {
-            state = State { images: Vec::new(), pages: BTreeMap::new(), open_image: 0 };
+// OCR configuration, which depends on the machine and account rather than the project
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct OcrSettings {
+    backend: OcrBackend,
+    textract: TextractSettings,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Projects {
+    projects: Vec<Project>,
+    last_opened: usize,
+    #[serde(default = "Projects::default_pixels_per_point")]
+    pixels_per_point: f32, // UI scale, which depends on the display rather than the project
+    #[serde(default)]
+    auto_save: bool, // save instead of asking, when switching projects or quitting with unsaved changes
+    #[serde(default)]
+    ocr: OcrSettings,
+}
+
+impl Default for Projects {
+    fn default() -> Self {
+        let project = |name: &str, annotations: &str, images: &str, scale| Project {
+            name: name.to_string(),
+            annotations: annotations.to_string(),
+            images: images.to_string(),
+            scale,
+            layout: None,
+        };
+        Self {
+            projects: vec![
+                project("Scrapbook 1", "annotations/annotations.yaml", "../scrapbook-images/jpeg1/pages/", 0.75),
+                project("Scrapbook 2", "annotations/annotations2.yaml", "../scrapbook-images/jpeg2/", 0.5),
+                project("Scrapbook 3", "annotations/annotations3.yaml", "../scrapbook-images/jpeg3/", 0.125),
+            ],
+            last_opened: 2,
+            pixels_per_point: Self::default_pixels_per_point(),
+            auto_save: false,
+            ocr: OcrSettings::default(),
         }
+    }
+}
 
-        for page in state.pages.values_mut() {
-            if page.date == None {
-                page.date = Some(String::new());
-            }
-            if page.summary == None {
-                page.summary = Some(String::new());
-            }
+impl Projects {
+    fn default_pixels_per_point() -> f32 {
+        2.0
+    }
+
+    fn load() -> Self {
+        let mut projects: Self = File::open(PROJECTS_FILENAME).ok()
+            .and_then(|file| serde_yaml::from_reader(file).ok())
+            .unwrap_or_default();
+        if projects.projects.is_empty() {
+            projects = Self::default();
         }
+        projects.last_opened = projects.last_opened.min(projects.projects.len() - 1);
+        projects.pixels_per_point = projects.pixels_per_point.clamp(MIN_PIXELS_PER_POINT, MAX_PIXELS_PER_POINT);
+        projects
+    }
+
+    fn save(&self) {
+        let file = File::create(PROJECTS_FILENAME).unwrap();
+        serde_yaml::to_writer(file, self).unwrap();
+    }
+}
+
+const PROJECTS_FILENAME: &str = "annotations/projects.yaml";
+
+// Screen-space width of the sidebar down the right of the window
+const SIDEBAR_WIDTH: f32 = 400.0;
+
+// Estimated height of an article in the sidebar, until it's been drawn
+const ARTICLE_ROW_HEIGHT: f32 = 40.0;
+
+const CROSS_FADE_SECONDS: f64 = 0.25;
+
+// Standard deviation of luma below which a page is probably blank
+const BLANK_STDDEV: f32 = 6.0;
+
+// Confidence (percent) that's shown as fully red, with 100% as green
+const LOW_CONFIDENCE: f32 = 80.0;
+
+// Largest size of the minimap, in screen-space
+const MINIMAP_SIZE: Vec2 = Vec2::new(300.0, 150.0);
+
+// How much consecutive regions overlap when stepping with Page Up/Down, as a fraction of the viewport
+const REGION_OVERLAP: f32 = 0.1;
+
+// Screen-space length the ruler's scale bar is at most
+const RULER_LENGTH: f32 = 200.0;
+
+// Limits on the screen-space size of vertex handles with an image-space radius
+const MIN_VERTEX_RADIUS: f32 = 2.0;
+const MAX_VERTEX_RADIUS: f32 = 12.0;
+
+// Number of extractions to remember
+const HISTORY_LENGTH: usize = 20;
+
+// Number of edits that can be undone on each page
+const UNDO_LENGTH: usize = 50;
+
+const MIN_SCALE: f32 = 0.02;
+const MAX_SCALE: f32 = 4.0;
+
+// Polys smaller than this many square pixels are probably misclicks, and OCR would reject them anyway
+const MIN_CROP_AREA: f32 = 16.0;
+
+// US dollars per OCR call, for estimating what a batch will cost (Textract's DetectDocumentText
+// list price, for the first million pages a month)
+const OCR_COST: f64 = 0.0015;
+
+// Samples along each axis of a pixel when anti-aliasing the mask
+const MASK_SAMPLES: u32 = 4;
+
+// Lines at least this much taller than the median line in a crop are probably a headline
+const HEADLINE_RATIO: f32 = 1.4;
+
+// Lines are fragments of the same printed line if they overlap vertically by this fraction of
+// the shorter one's height, and the gap between them is less than this many line heights
+const SPLIT_LINE_OVERLAP: f32 = 0.5;
+const SPLIT_LINE_GAP: f32 = 2.0;
+
+// Range of the UI scale setting
+const MIN_PIXELS_PER_POINT: f32 = 0.75;
+const MAX_PIXELS_PER_POINT: f32 = 4.0;
+
+// Screen-space height of the fast entry panel
+const FAST_ENTRY_HEIGHT: f32 = 260.0;
+
+const DUPLICATE_TEXT_THRESHOLD: f32 = 0.8;
+const DUPLICATE_OVERLAP_THRESHOLD: f32 = 0.5;
+
+impl Default for MyApp {
+    fn default() -> Self {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+
+        let projects = Projects::load();
+        let project = projects.projects[projects.last_opened].clone();
+        let state = project.load_state();
+        let scale = project.scale;
 
         let image = ColorImage::new([1, 1], Color32::BLACK);
         let mut ret = Self {
             runtime,
+            projects,
+            project,
+            leaving: None,
+            quitting: false,
+            saved: String::new(),
+            new_project: Project { name: String::new(), annotations: String::new(), images: String::new(), scale: 0.25, layout: None },
             image: RgbImage::new(1, 1),
             retained_image: RetainedImage::from_color_image("black", image.clone()),
+            fade_from: None,
+            images_problem: None,
+            images_path_edit: String::new(),
+            loading: None,
             crop_image: RgbImage::new(1, 1),
+            crop_rect: Rect::NOTHING,
             retained_crop: RetainedImage::from_color_image("black", image.clone()),
+            retained_source_crop: RetainedImage::from_color_image("black", image.clone()),
+            crop_outline: false,
+            crop_mask: Vec::new(),
+            histogram: vec![0; 256],
+            levels: (0, 255),
+            preset_fill: None,
+            new_preset: String::new(),
             vertexes: Vec::new(),
+            drawing: false,
+            holes: Vec::new(),
+            previous_vertexes: None,
             lines: Vec::new(),
+            provenance: None,
+            jpeg_size: 0,
             draft_text: String::new(),
+            draft_appended: false,
+            draft_cursor: (0, 0),
+            separator: String::new(),
+            history: std::collections::VecDeque::new(),
+            show_history: false,
+            scrap_selection: BTreeSet::new(),
+            copied_poly: None,
+            scale,
+            pending_navigation: None,
+            status: String::new(),
             offset: Vec2::ZERO,
 
             state,
             open_article: None,
+            selected_poly: None,
+            duplicates: None,
+            out_of_bounds: Vec::new(),
+            text_changes: None,
+            last_text_changes: Vec::new(),
+            gremlins: None,
+            undo: HashMap::new(),
+            editing_text: None,
+            external_edit: None,
+            transform: Transform::CollapseWhitespace,
+            transform_pages: (0, usize::MAX),
+            transform_open_article: false,
+            readonly: false,
+            show_boxes: true,
+            article_heights: HashMap::new(),
+            likely_blank: false,
+            skip_blanks: false,
+            tag_filter: None,
+            insert_image: String::new(),
+            page_input: None,
+            batch_summary: None,
+            blank_detection: None,
+            batch_estimate: None,
+            statistics: None,
+            choosing_append_target: false,
+            draft_page: None,
+            selection_highlight: Vec::new(),
+            fast_entry: false,
+            box_start: None,
+            new_tag: String::new(),
+            show_minimap: true,
+            show_ruler: false,
+            hide_chrome: false,
+            measuring: false,
+            measure: Vec::new(),
+            recent_first: false,
+            export_pages: (0, usize::MAX),
         };
-        ret.load_image();
+        ret.saved = serde_yaml::to_string(&ret.state).unwrap();
+        ret.check_images();
+        if ret.images_problem.is_none() {
+            ret.load_image();
+        }
         ret
     }
 }
 
 impl State {
-    fn page(&mut self) -> &mut Page {
-        self.pages.entry(self.images[self.open_image].clone()).or_insert_with(|| Page { date: Some(String::new()), summary: Some(String::new()), articles: Vec::new() })
-    }
-}
+    fn load(filename: &str) -> Self {
+        let mut state: State;
+        if let Ok(file) = File::open(filename) {
+            state = serde_yaml::from_reader(file).unwrap();
+        } else {
+            state = State { images: Vec::new(), pages: BTreeMap::new(), open_image: 0, settings: Settings::default(), next_article_id: 1 };
+        }
 
-fn cmp_f32(a: &f32, b: &f32) -> Ordering {
-    a.partial_cmp(&b).unwrap()
-}
+        state.assign_article_ids();
 
-impl MyApp {
-    fn load_image(&mut self) {
-        let mut lines: Vec<Line> = Vec::new();
+        // The file might have been edited to remove images
+        state.open_image = state.open_image.min(state.images.len().saturating_sub(1));
 
-        let image = image::load_from_memory(
-            std::fs::read(format!("{}{}", JPEG_PATH, self.state.images[self.state.open_image])).unwrap().as_ref()
-        )
-        .unwrap().to_rgb8();
-        let egui_image = ColorImage::from_rgb([image.width() as _, image.height() as _], image.as_flat_samples().as_slice());
-        let retained_image = RetainedImage::from_color_image("image", egui_image);
+        for page in state.pages.values_mut() {
+            if page.date.is_none() {
+                page.date = Some(String::new());
+            }
+            if page.summary.is_none() {
+                page.summary = Some(String::new());
+            }
+        }
 
-        self.lines = lines;
-        self.image = image;
-        self.retained_image = retained_image;
+        state
     }
 
-    fn save(&mut self) {
-        let file = File::create(ANNOTATIONS_FILENAME).unwrap();
-        serde_yaml::to_writer(file, &self.state).unwrap();
+    fn round_polys(&mut self) {
+        for article in self.pages.values_mut().flat_map(|page| &mut page.articles) {
+            article.round_polys();
+        }
     }
 
-    fn new_article(&mut self) {
-        let page = self.state.page();
-        let id = page.articles.len();
-        page.articles.push(Article {
-            polys: Vec::new(),
-            text: String::new(),
-        });
-        self.open_article = Some(id);
+    fn page(&mut self) -> &mut Page {
+        let filename = self.images[self.open_image].clone();
+        self.page_named(filename)
     }
 
-    fn merge_lines(lines: Vec<Line>, image_width: f32) -> String {
-        let mut text = String::new();
+    // Every page tag in use, for the tag filter
+    fn tags(&self) -> BTreeSet<String> {
+        self.pages.values().flat_map(|page| page.tags.iter().cloned()).collect()
+    }
 
-        let mut dehyphenating = false;
-        for (i, line) in lines.iter().enumerate() {
-            let mut start = 0;
-            if dehyphenating {
-                // Add the first word after a hyphen onto the previous line
-                if let Some(space) = line.text.find(" ") {
-                    text.push_str(&line.text[0..space]);
-                    text.push_str("\n");
-                    start = space + 1;
-                }
-            } else {
-                // Try to detect paragraph indents
-                if i > 0 && i + 1 < lines.len() {
-                    let x0 = lines[i - 1].left * image_width;
-                    let x1 = lines[i + 0].left * image_width;
-                    let x2 = lines[i + 1].left * image_width;
-                    let min = 8.0;
-                    let max = 40.0;
-                    if min < x1 - x0 && x1 - x0 < max && min < x1 - x2 && x1 - x2 < max {
-                        text.push_str("\n");
-                    }
+    fn page_named(&mut self, filename: String) -> &mut Page {
+        self.pages.entry(filename).or_insert_with(|| Page { date: Some(String::new()), summary: Some(String::new()), articles: Vec::new(), notes: String::new(), open_article: None, blank: false, tags: Vec::new(), scratchpad: Vec::new() })
+    }
+
+    // Give IDs to any articles loaded from older files, in page order so it's deterministic
+    fn assign_article_ids(&mut self) {
+        let max_id = self.pages.values().flat_map(|page| &page.articles).map(|article| article.id).max().unwrap_or(0);
+        self.next_article_id = max_id + 1;
+        for page in self.pages.values_mut() {
+            for article in &mut page.articles {
+                if article.id == 0 {
+                    article.id = self.next_article_id;
+                    self.next_article_id += 1;
                 }
             }
-            if line.text.ends_with("-") {
-                text.push_str(&line.text[start..line.text.len() - 1]);
-                dehyphenating = true;
-            } else {
-                text.push_str(&line.text[start..]);
-                text.push_str("\n");
-                dehyphenating = false;
-            }
         }
+    }
 
-        text
+    fn new_article_id(&mut self) -> u64 {
+        let id = self.next_article_id;
+        self.next_article_id += 1;
+        id
     }
 
-    // Test if line (ox, oy)--(inf, oy) intersects (ax, ay)--(bx, by)
-    fn ray_intersect(ox: f32, oy: f32, ax: f32, ay: f32, bx: f32, by: f32) -> bool {
-        // Test if a,b on opposite sides of o--inf:
-        if (ay - oy).signum() == (by - oy).signum() {
-            return false;
+    // Sort the images list, keeping the same image open
+    fn sort_images(&mut self, images_path: &str) {
+        let open = self.images.get(self.open_image).cloned();
+        match self.settings.image_order {
+            ImageOrder::Lexical => self.images.sort(),
+            ImageOrder::Natural => self.images.sort_by(|a, b| natural_cmp(a, b)),
+            ImageOrder::Modified => self.images.sort_by_cached_key(|image| {
+                std::fs::metadata(format!("{}{}", images_path, image)).and_then(|m| m.modified()).ok()
+            }),
+        }
+        if let Some(open) = open {
+            self.open_image = self.images.iter().position(|image| *image == open).unwrap();
         }
-        // Test if o,inf on opposite sides of a--b:
-        //  s0 = (ox-ax, oy-ay) . (by-ay, ax-bx)
-        //  s1 = (ox+inf-ax, oy-ay) . (by-ay, ax-bx) =~ inf*(by-ay)
-        let s0 = ((ox - ax) * (by - ay) + (oy - ay) * (ax - bx)).signum();
-        let s1 = (by - ay).signum();
-        return s0 != s1;
     }
 
-    fn extract_image(&mut self) -> Vec<u8> {
-        let x0 = self.vertexes.iter().map(|p| p.x).min_by(cmp_f32).unwrap();
-        let x1 = self.vertexes.iter().map(|p| p.x).max_by(cmp_f32).unwrap();
-        let y0 = self.vertexes.iter().map(|p| p.y).min_by(cmp_f32).unwrap();
-        let y1 = self.vertexes.iter().map(|p| p.y).max_by(cmp_f32).unwrap();
-
-        let margin = 4.0;
-        let x0 = ((x0 - margin) as i32).clamp(0, self.image.width() as i32) as u32;
-        let x1 = ((x1 + margin) as i32).clamp(0, self.image.width() as i32) as u32;
-        let y0 = ((y0 - margin) as i32).clamp(0, self.image.height() as i32) as u32;
-        let y1 = ((y1 + margin) as i32).clamp(0, self.image.height() as i32) as u32;
-
-        let mut vertexes = self.vertexes.clone();
-        vertexes.push(self.vertexes[0]); // close the shape
-        let lines: Vec<_> = vertexes.windows(2).map(|vs| {
-            (vs[0].x - x0 as f32, vs[0].y - y0 as f32, vs[1].x - x0 as f32, vs[1].y - y0 as f32)
-        }).collect();
-
-        let mut image = RgbImage::new(x1 - x0, y1 - y0);
-        for (x, y, p) in image.enumerate_pixels_mut() {
-            let xf = x as f32;
-            let yf = y as f32;
-            let crossings = lines.iter().filter(|line| {
-                Self::ray_intersect(xf, yf, line.0, line.1, line.2, line.3)
-            }).count();
-            let inside = (crossings % 2) == 1;
-            if inside {
-                *p = *self.image.get_pixel(x0 + x, y0 + y);
-            } else {
-                *p = image::Rgb([48, 48, 48]);
+    // Add any images in images_path that aren't in the list yet. Returns how many were added
+    fn scan_images(&mut self, images_path: &str) -> std::io::Result<usize> {
+        let mut added = 0;
+        for entry in std::fs::read_dir(images_path)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            let lower = name.to_lowercase();
+            if [".jpg", ".jpeg", ".png", ".tif", ".tiff"].iter().any(|ext| lower.ends_with(ext)) && !self.images.contains(&name) {
+                self.images.push(name);
+                added += 1;
             }
         }
+        self.sort_images(images_path);
+        Ok(added)
+    }
 
-        let egui_image = ColorImage::from_rgb([image.width() as _, image.height() as _], image.as_flat_samples().as_slice());
-        self.retained_crop = RetainedImage::from_color_image("crop", egui_image);
+    // Pages are keyed by filename, so their annotations follow the images around the list.
+    // A removed image's annotations stay in the file, and come back if it's inserted again
+    fn insert_image(&mut self, index: usize, filename: String) {
+        self.images.insert(index, filename);
+        if index <= self.open_image && self.images.len() > 1 {
+            self.open_image += 1;
+        }
+    }
 
-        let mut bytes: Vec<u8> = Vec::new();
-        image.write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(&mut Cursor::new(&mut bytes), 90)).unwrap();
+    fn remove_image(&mut self, index: usize) -> String {
+        let filename = self.images.remove(index);
+        if index < self.open_image || self.open_image == self.images.len() {
+            self.open_image = self.open_image.saturating_sub(1);
+        }
+        filename
+    }
 
-        self.crop_image = image;
+    fn move_image(&mut self, from: usize, to: usize) {
+        let filename = self.images.remove(from);
+        self.images.insert(to, filename);
+        if self.open_image == from {
+            self.open_image = to;
+        } else if from < self.open_image && self.open_image <= to {
+            self.open_image -= 1;
+        } else if to <= self.open_image && self.open_image < from {
+            self.open_image += 1;
+        }
+    }
+}
 
-        bytes
+impl Article {
+    fn new(id: u64, text: String) -> Self {
+        let now = Some(unix_time());
+        Self { id, polys: Vec::new(), text, raw_ocr: Vec::new(), holes: Vec::new(), provenance: Vec::new(), verified: false, source: None, kind: ArticleKind::Text, caption: None, crop: None, created_at: now, modified_at: now }
     }
 
-    async fn extract_text(&self, image_bytes: Vec<u8>) -> (String, RgbImage) {
-        let config = aws_config::defaults(aws_config::BehaviorVersion::v2024_03_28()).region("eu-west-2").load().await;
-        let client = aws_sdk_textract::Client::new(&config);
+    fn touch(&mut self) {
+        self.modified_at = Some(unix_time());
+    }
 
-        let res = client
-            .detect_document_text()
-            .document(aws_sdk_textract::types::Document::builder().bytes(aws_sdk_textract::primitives::Blob::new(image_bytes)).build())
-            .send()
-            .await;
+    fn push_poly(&mut self, vertexes: Vec<Pos2>, holes: Vec<Vec<Pos2>>, raw_ocr: Option<RawOcr>, provenance: Option<Provenance>) {
+        self.raw_ocr.resize(self.polys.len(), None);
+        if !holes.is_empty() || !self.holes.is_empty() {
+            self.holes.resize(self.polys.len(), Vec::new());
+            self.holes.push(holes);
+        }
+        if provenance.is_some() || !self.provenance.is_empty() {
+            self.provenance.resize(self.polys.len(), None);
+            self.provenance.push(provenance);
+        }
+        self.polys.push(vertexes);
+        self.raw_ocr.push(raw_ocr);
+        self.touch();
+    }
 
-        match res {
-            Ok(doc) => {
-                let mut lines: Vec<Line> = Vec::new();
+    fn remove_poly(&mut self, i: usize) {
+        self.polys.remove(i);
+        if i < self.raw_ocr.len() {
+            self.raw_ocr.remove(i);
+        }
+        if i < self.holes.len() {
+            self.holes.remove(i);
+        }
+        if i < self.provenance.len() {
+            self.provenance.remove(i);
+        }
+        self.touch();
+    }
 
-                for block in doc.blocks() {
-                    if *block.block_type().unwrap() == aws_sdk_textract::types::BlockType::Line {
-                        let points: Vec<_> = block.geometry().unwrap().polygon()
-                            .iter()
-                            .map(|pt| {
-                                Vec2::new(pt.x(), pt.y())
-                            })
-                            .collect();
+    fn append_scrap(&mut self, scrap: Scrap) {
+        self.text.push_str(&scrap.text);
+        self.text.push('\n');
+        self.push_poly(scrap.vertexes, scrap.holes, scrap.raw_ocr, scrap.provenance);
+    }
 
-                        let bbox = block.geometry().unwrap().bounding_box().unwrap();
+    fn append_polys(&mut self, other: Article) {
+        for (i, vertexes) in other.polys.into_iter().enumerate() {
+            self.push_poly(vertexes, other.holes.get(i).cloned().unwrap_or_default(), other.raw_ocr.get(i).cloned().flatten(), other.provenance.get(i).cloned().flatten());
+        }
+    }
 
-                        let mid = Vec2::new(bbox.left() + bbox.width() / 2.0, bbox.top() + bbox.height() / 2.0);
-                        let left = bbox.left();
+    // Sub-pixel precision means nothing for hand-drawn polys, and whole numbers are shorter to store
+    fn round_polys(&mut self) {
+        for p in self.polys.iter_mut().chain(self.holes.iter_mut().flatten()).flatten() {
+            *p = p.round();
+        }
+    }
 
-                        lines.push(Line {
-                            text: block.text().unwrap().to_string(),
-                            bbox: Rect::from_min_size(Pos2::new(bbox.left(), bbox.top()), Vec2::new(bbox.width(), bbox.height())),
-                            points,
-                            left,
-                            mid,
-                        });
-                    }
-                }
+    fn poly_holes(&self, i: usize) -> &[Vec<Pos2>] {
+        self.holes.get(i).map_or(&[], |holes| holes)
+    }
 
-                // Sort top-to-bottom, with a fudge for simple cases where a line is split into multiple Lines
-                // and we want to do them left-to-right
-                lines.sort_by(|a, b| {
-                    let am = a.mid.y + a.left / 40.0;
-                    let bm = b.mid.y + b.left / 40.0;
-                    am.partial_cmp(&bm).unwrap()
-                });
+    // Where the selected byte range of the text is on the image, if it's still exactly what was
+    // merged from the stored OCR
+    fn selection_rects(&self, settings: &Settings, selection: &std::ops::Range<usize>) -> Option<Vec<Rect>> {
+        let mut text = String::new();
+        let mut rects = Vec::new();
+        for i in 0..self.polys.len() {
+            let ocr = self.raw_ocr.get(i)?.as_ref()?;
+            ocr.join().apply(&mut text);
+            let (merged, offsets) = MyApp::merge_lines_with_offsets(&ocr.lines, ocr.width, settings);
+            rects.extend(MyApp::selection_rects(&ocr.lines, &offsets, ocr.crop?, text.len(), selection));
+            text.push_str(merged.trim_end());
+            text.push('\n');
+        }
+        (text == self.text).then_some(rects)
+    }
 
-                return (Self::merge_lines(lines, self.retained_crop.width() as f32), self.crop_image.clone());
-            },
-            Err(err) => {
-                return (format!("Error: {:?}", err), self.crop_image.clone());
-            }
+    // Rebuild the text from the stored OCR output, if every poly has it
+    fn remerge(&self, settings: &Settings) -> Option<String> {
+        if self.polys.is_empty() {
+            return None;
         }
+        let mut text = String::new();
+        for i in 0..self.polys.len() {
+            let ocr = self.raw_ocr.get(i)?.as_ref()?;
+            ocr.join().apply(&mut text);
+            text.push_str(MyApp::merge_lines(ocr.lines.clone(), ocr.width, settings).trim_end());
+            text.push('\n');
+        }
+        Some(text)
     }
 }
 
-struct Scaler {
-    scale: f32, // screen-space units per image-space pixel
-    viewport: Vec2, // size in screen-space
-    offset: Vec2, // screen-space coords
-    image_rect: Rect, // screen-space coords of viewport
+// Pages get created just by visiting them, so leave out the ones with nothing on them, or flicking
+// through the images would change the file. Everything else is already deterministic: the map is
+// sorted by filename, articles stay in reading order until they're moved, and floats are written
+// in their shortest form that reads back exactly
+fn serialize_pages<S: serde::Serializer>(pages: &BTreeMap<String, Page>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(pages.iter().filter(|(_, page)| !page.is_empty()))
 }
 
-impl Scaler {
-    fn screen_to_image(&self, screen: Pos2) -> Pos2 {
-        ((screen.to_vec2() - self.image_rect.left_top().to_vec2() + self.offset) / self.scale).to_pos2()
+impl Page {
+    fn is_empty(&self) -> bool {
+        self.date.as_deref().unwrap_or("").is_empty()
+            && self.summary.as_deref().unwrap_or("").is_empty()
+            && self.articles.is_empty()
+            && self.notes.is_empty()
+            && self.open_article.is_none()
+            && !self.blank
+            && self.tags.is_empty()
+            && self.scratchpad.is_empty()
     }
 
-    fn image_to_screen(&self, image: Pos2) -> Pos2 {
-        ((image.to_vec2() * self.scale) - self.offset + self.image_rect.left_top().to_vec2()).to_pos2()
+    fn article_index(&self, id: u64) -> Option<usize> {
+        self.articles.iter().position(|article| article.id == id)
+    }
+
+    fn article_mut(&mut self, id: u64) -> Option<&mut Article> {
+        self.articles.iter_mut().find(|article| article.id == id)
     }
 }
 
-impl eframe::App for MyApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        ctx.set_pixels_per_point(2.0);
+fn unix_time() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let scale = DEFAULT_SCALE;
-            let viewport = Vec2::new(1920.0, 1080.0 - 48.0);
+// Roughly how long ago a unix_time() was, like "3 days ago"
+fn format_age(time: u64) -> String {
+    let secs = unix_time().saturating_sub(time);
+    let (n, unit) = match secs {
+        0..60 => return "just now".to_owned(),
+        60..3600 => (secs / 60, "minute"),
+        3600..86400 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
 
-            let show_boxes = !ui.input(|i| i.modifiers.alt);
+fn cmp_f32(a: &f32, b: &f32) -> Ordering {
+    a.partial_cmp(b).unwrap()
+}
 
-            let response = ui.allocate_response(viewport, Sense::click_and_drag());
-            let image_rect = response.rect;
+// Compare strings with runs of digits compared numerically, like natord
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+        let mut digits = String::new();
+        while let Some(&c) = chars.peek() {
+            if !c.is_ascii_digit() {
+                break;
+            }
+            digits.push(c);
+            chars.next();
+        }
+        digits
+    }
 
-            let scaler = Scaler {
-                scale,
-                viewport,
-                offset: self.offset,
-                image_rect,
-            };
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let da = take_digits(&mut a);
+                let db = take_digits(&mut b);
+                let na = da.trim_start_matches('0');
+                let nb = db.trim_start_matches('0');
+                // Longer (without leading zeros) means bigger; then fewer leading zeros first
+                let ord = na.len().cmp(&nb.len()).then_with(|| na.cmp(nb)).then_with(|| da.len().cmp(&db.len()));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                let ord = ca.cmp(cb);
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
 
-            let mut mesh = egui::Mesh::with_texture(self.retained_image.texture_id(ctx));
-            mesh.add_rect_with_uv(
-                image_rect,
-                Rect::from_min_max(
-                    (self.offset / (self.retained_image.size_vec2() * scale)).to_pos2(),
-                    ((self.offset + viewport) / (self.retained_image.size_vec2() * scale)).to_pos2(),
-                ),
-                Color32::WHITE,
-            );
-            ui.painter().add(Shape::mesh(mesh));
+impl MyApp {
+    // Decode based on the file's content rather than its extension, since scans are sometimes mislabeled.
+    // Returns the detected format too
+    fn decode_image(path: &str) -> Result<(RgbImage, image::ImageFormat), String> {
+        let bytes = std::fs::read(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+        let format = image::guess_format(&bytes).map_err(|err| format!("Unrecognised image format in {}: {}", path, err))?;
+        let image = image::load_from_memory_with_format(&bytes, format)
+            .map_err(|err| format!("Failed to decode {} as {:?}: {}", path, format, err))?;
+        Ok((image.to_rgb8(), format))
+    }
 
-            if show_boxes {
-                for article in &self.state.page().articles {
-                    for vertexes in &article.polys {
-                        // egui assumes convex, which is not true
-                        let path = PathShape {
-                            points: vertexes.iter().map(|&p| scaler.image_to_screen(p)).collect(),
-                            closed: true,
-                            fill: Color32::from_rgba_unmultiplied(0, 0, 0, 50),
-                            stroke: PathStroke::NONE,
-                        };
-                        ui.painter().add(path);
-                    }
+    fn open_image_path(&self) -> String {
+        format!("{}{}", self.project.images, self.state.images[self.state.open_image])
+    }
+
+    // Load the open image, blocking until it's decoded
+    fn load_image(&mut self) {
+        if self.state.images.is_empty() {
+            return;
+        }
+        self.loading = None;
+        self.reset_page();
+        let image = Self::decode_image(&self.open_image_path());
+        self.image_loaded(image);
+    }
+
+    // Load the open image on another thread; the old image stays visible until update() gets the new one
+    fn load_image_async(&mut self) {
+        if self.state.images.is_empty() {
+            return;
+        }
+        self.reset_page();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let path = self.open_image_path();
+        std::thread::spawn(move || {
+            // If we've moved on to another image, nobody is listening any more
+            let _ = sender.send(Self::decode_image(&path));
+        });
+        self.loading = Some(receiver);
+    }
+
+    fn image_loaded(&mut self, image: Result<(RgbImage, image::ImageFormat), String>) {
+        let image = match image {
+            Ok((image, format)) => {
+                let filename = &self.state.images[self.state.open_image];
+                if image::ImageFormat::from_path(filename).ok() != Some(format) {
+                    self.status = format!("{} is actually {:?}", filename, format);
                 }
+                image
             }
-
-            if response.dragged_by(egui::PointerButton::Secondary) {
-                self.offset -= response.drag_delta();
+            Err(err) => {
+                log::error!("{}", err);
+                self.status = err;
+                RgbImage::new(1, 1)
             }
+        };
+        let egui_image = ColorImage::from_rgb([image.width() as _, image.height() as _], image.as_flat_samples().as_slice());
+        self.retained_image = RetainedImage::from_color_image("image", egui_image);
+        self.likely_blank = Self::is_blank(&image);
+        self.image = image;
+        self.out_of_bounds = self.find_out_of_bounds();
+    }
 
-            if !self.vertexes.is_empty() && response.clicked_by(egui::PointerButton::Middle) {
-                self.vertexes.pop();
+    // Whether the image is close enough to a uniform colour that there's probably nothing on it
+    fn is_blank(image: &RgbImage) -> bool {
+        let samples: Vec<f32> = image.pixels().step_by(7).map(|p| Self::luma(p) as f32).collect();
+        if samples.is_empty() {
+            return false;
+        }
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        let variance = samples.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / samples.len() as f32;
+        variance.sqrt() < BLANK_STDDEV
+    }
+
+    // Start checking every page without any articles for whether it looks blank
+    fn detect_blank_pages(&mut self) {
+        let unannotated = |filename: &&String| !self.state.pages.get(*filename).is_some_and(|page| page.blank || !page.articles.is_empty());
+        let filenames: Vec<String> = self.state.images.iter().filter(unannotated).cloned().collect();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let images = self.project.images.clone();
+        let pages = filenames.len();
+        std::thread::spawn(move || {
+            for filename in filenames {
+                let blank = Self::decode_image(&format!("{}{}", images, filename)).map(|(image, _)| Self::is_blank(&image));
+                // Stop if the detection has been abandoned
+                if sender.send((filename, blank)).is_err() {
+                    break;
+                }
             }
+        });
+        self.blank_detection = Some(BlankDetection { receiver, pages, checked: 0, blank: Vec::new(), problems: Vec::new() });
+    }
 
-            if response.clicked_by(egui::PointerButton::Primary) {
-                if !ctx.input(|i| i.modifiers.shift) {
-                    self.vertexes.clear();
+    // Collect the results of detect_blank_pages, and mark the pages once they've all been checked.
+    // Any that have been annotated meanwhile are left alone
+    fn poll_blank_detection(&mut self, ctx: &egui::Context) {
+        let Some(detection) = &mut self.blank_detection else {
+            return;
+        };
+        loop {
+            match detection.receiver.try_recv() {
+                Ok((filename, blank)) => {
+                    detection.checked += 1;
+                    match blank {
+                        Ok(true) => detection.blank.push(filename),
+                        Ok(false) => {}
+                        Err(err) => detection.problems.push(err),
+                    }
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => {
+                    self.status = format!("Checking pages for blanks ({}/{})...", detection.checked, detection.pages);
+                    ctx.request_repaint_after(std::time::Duration::from_millis(100));
+                    return;
                 }
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => break,
+            }
+        }
 
-                self.vertexes.push(scaler.screen_to_image(response.interact_pointer_pos().unwrap()));
+        let detection = self.blank_detection.take().unwrap();
+        let mut marked = Vec::new();
+        for filename in detection.blank {
+            let page = self.state.page_named(filename.clone());
+            if !page.blank && page.articles.is_empty() {
+                page.blank = true;
+                marked.push(filename);
             }
+        }
+        self.status = format!("Marked {} pages as blank", marked.len());
+        self.batch_summary = Some(BatchSummary { title: "Detect blanks", pages: marked.len(), changed: marked.len(), problems: detection.problems, undo: BatchUndo::Blanks(marked) });
+    }
 
-            let adding_vertex = !self.vertexes.is_empty() && ctx.input(|i| i.modifiers.shift);
-            let mut temp_vertex = false;
-            if adding_vertex {
-                if let Some(p) = response.hover_pos() {
-                    self.vertexes.push(scaler.screen_to_image(p));
-                    temp_vertex = true;
+    // Whether < > and exports should include this image, given the blank and tag filters
+    fn page_included(&self, filename: &str) -> bool {
+        let page = self.state.pages.get(filename);
+        if self.skip_blanks && page.is_some_and(|page| page.blank) {
+            return false;
+        }
+        match &self.tag_filter {
+            Some(tag) => page.is_some_and(|page| page.tags.contains(tag)),
+            None => true,
+        }
+    }
+
+    // The adjacent image in the given direction, skipping blank or untagged pages if requested
+    fn step_image(&self, forwards: bool) -> Option<usize> {
+        let mut index = self.state.open_image;
+        loop {
+            index = if forwards { index + 1 } else { index.checked_sub(1)? };
+            let filename = self.state.images.get(index)?;
+            if self.page_included(filename) {
+                return Some(index);
+            }
+        }
+    }
+
+    // The open page's tags, as removable buttons, plus a box to add another
+    fn tags_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Tags");
+            let mut remove = None;
+            for (i, tag) in self.state.page().tags.iter().enumerate() {
+                if ui.add_enabled(!self.readonly, egui::Button::new(format!("{} x", tag)).small()).on_hover_text("Remove this tag").clicked() {
+                    remove = Some(i);
                 }
             }
+            if let Some(i) = remove {
+                self.state.page().tags.remove(i);
+            }
 
-            if show_boxes {
-                for &vertex in &self.vertexes {
-                    ui.painter().add(Shape::Circle(
-                        CircleShape {
-                            center: scaler.image_to_screen(vertex),
-                            radius: 3.0,
-                            fill: Color32::TRANSPARENT,
-                            stroke: Stroke::new(1.0, Color32::from_rgba_unmultiplied(255, 0, 0, 255))
-                        }
-                    ));
+            let response = ui.add_enabled(!self.readonly, egui::TextEdit::singleline(&mut self.new_tag).desired_width(80.0).hint_text("add tag"));
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                let tag = self.new_tag.trim().to_lowercase();
+                if !tag.is_empty() && !self.state.page().tags.contains(&tag) {
+                    self.state.page().tags.push(tag);
                 }
-                ui.painter().add(Shape::Path(
-                    PathShape {
-                        points: self.vertexes.iter().map(|&p| scaler.image_to_screen(p)).collect(),
-                        closed: !adding_vertex,
-                        fill: Color32::TRANSPARENT,
-                        stroke: PathStroke::new(2.0, Color32::from_rgba_unmultiplied(255, 0, 0, 255))
+                self.new_tag.clear();
+                response.request_focus();
+            }
+
+            let tags = self.state.tags();
+            if self.tag_filter.as_ref().is_some_and(|tag| !tags.contains(tag)) {
+                self.tag_filter = None;
+            }
+            egui::ComboBox::from_id_salt("tag_filter")
+                .selected_text(match &self.tag_filter {
+                    Some(tag) => format!("Only \"{}\"", tag),
+                    None => "All pages".to_owned(),
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.tag_filter, None, "All pages");
+                    for tag in tags {
+                        let label = format!("Only \"{}\"", tag);
+                        ui.selectable_value(&mut self.tag_filter, Some(tag), label);
                     }
-                ));
+                })
+                .response
+                .on_hover_text("Restrict < > and exports to pages with this tag");
+        });
+    }
+
+    // Move the view to the next or previous viewport-sized region, left to right and then top to bottom
+    fn step_region(&mut self, visible: Vec2, forwards: bool) {
+        let image_size = self.retained_image.size_vec2() * self.scale;
+        let step = visible * (1.0 - REGION_OVERLAP);
+        let last = (image_size - visible).max(Vec2::ZERO);
+        if forwards {
+            if self.offset.x < last.x - 1.0 {
+                self.offset.x = (self.offset.x + step.x).min(last.x);
+            } else if self.offset.y < last.y - 1.0 {
+                self.offset = Vec2::new(0.0, (self.offset.y + step.y).min(last.y));
             }
+        } else if self.offset.x > 1.0 {
+            self.offset.x = (self.offset.x - step.x).max(0.0);
+        } else if self.offset.y > 1.0 {
+            self.offset = Vec2::new(last.x, (self.offset.y - step.y).max(0.0));
+        }
+    }
 
-            if temp_vertex {
-                self.vertexes.pop();
+    // The largest of 1, 2 or 5 times a power of ten that's no more than max
+    fn round_length(max: f32) -> f32 {
+        let power = 10f32.powf(max.log10().floor());
+        [5.0, 2.0, 1.0].into_iter().map(|m| m * power).find(|&length| length <= max).unwrap_or(power)
+    }
+
+    // An image-space length in cm and inches, if the DPI is known
+    fn physical_length(pixels: f32, dpi: f32) -> String {
+        if dpi <= 0.0 {
+            return String::new();
+        }
+        let inches = pixels / dpi;
+        format!(" ({:.2} cm, {:.2} in)", inches * 2.54, inches)
+    }
+
+    // Scale bars in the bottom right corner: cm and inches if the DPI is known, or else pixels
+    fn ruler(&self, ui: &mut egui::Ui, visible: Rect) {
+        let dpi = self.state.settings.dpi;
+        let max_pixels = RULER_LENGTH / self.scale;
+        // (length in image pixels, label)
+        let bars: Vec<(f32, String)> = if dpi > 0.0 {
+            let cm = Self::round_length(max_pixels / dpi * 2.54);
+            let inches = Self::round_length(max_pixels / dpi);
+            let cm_label = if cm < 1.0 { format!("{} mm", cm * 10.0) } else { format!("{} cm", cm) };
+            vec![(cm / 2.54 * dpi, cm_label), (inches * dpi, format!("{} in", inches))]
+        } else {
+            let pixels = Self::round_length(max_pixels);
+            vec![(pixels, format!("{} px", pixels))]
+        };
+
+        let color = Color32::from_rgb(0, 192, 255);
+        let right = visible.right() - 16.0;
+        let mut y = visible.bottom() - 16.0;
+        for (pixels, label) in bars.iter().rev() {
+            let left = right - pixels * self.scale;
+            let background = Rect::from_min_max(Pos2::new(left - 4.0, y - 22.0), Pos2::new(right + 4.0, y + 4.0));
+            ui.painter().rect_filled(background, 2.0, Color32::from_black_alpha(160));
+            ui.painter().line_segment([Pos2::new(left, y), Pos2::new(right, y)], Stroke::new(2.0, color));
+            for x in [left, right] {
+                ui.painter().line_segment([Pos2::new(x, y - 6.0), Pos2::new(x, y)], Stroke::new(2.0, color));
             }
+            ui.painter().text(Pos2::new((left + right) / 2.0, y - 4.0), egui::Align2::CENTER_BOTTOM, label, FontId::new(12.0, FontFamily::Proportional), color);
+            y -= 30.0;
+        }
+    }
 
-            if self.vertexes.len() >= 4 {
-                let x1 = self.vertexes.iter().map(|p| p.x).max_by(|a, b| a.partial_cmp(&b).unwrap()).unwrap();
-                let y0 = self.vertexes.iter().map(|p| p.y).min_by(|a, b| a.partial_cmp(&b).unwrap()).unwrap();
+    // Overview of the whole image in the corner of the view, showing the visible region.
+    // Clicking or dragging on it moves the view there
+    fn minimap(&mut self, ui: &mut egui::Ui, visible: Rect) {
+        let image_size = self.retained_image.size_vec2();
+        let map_scale = f32::min(MINIMAP_SIZE.x / image_size.x, MINIMAP_SIZE.y / image_size.y);
+        let map_rect = Rect::from_min_size(
+            visible.left_bottom() + Vec2::new(8.0, -8.0 - image_size.y * map_scale),
+            image_size * map_scale,
+        );
 
-                ui.allocate_ui_at_rect(
-                    Rect::from_min_size(
-                        scaler.image_to_screen(Pos2::new(x1 + 20.0, y0 - 20.0)),
-                        Vec2::new(500.0, 200.0),
-                    ),
-                    |ui| {
-                        self.popup(ui);
-                    },
-                );
+        let response = ui.interact(map_rect, ui.id().with("minimap"), Sense::click_and_drag());
+        if let Some(pointer) = response.interact_pointer_pos() {
+            // Centre the view on the pointer
+            let target = (pointer - map_rect.min) / map_scale;
+            self.offset = target * self.scale - visible.size() / 2.0;
+        }
+
+        let mut mesh = egui::Mesh::with_texture(self.retained_image.texture_id(ui.ctx()));
+        mesh.add_rect_with_uv(map_rect, Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)), Color32::from_white_alpha(220));
+        ui.painter().rect_filled(map_rect.expand(2.0), 2.0, Color32::from_black_alpha(160));
+        ui.painter().add(Shape::mesh(mesh));
+
+        let view = Rect::from_min_size((self.offset / self.scale * map_scale).to_pos2(), visible.size() / self.scale * map_scale)
+            .translate(map_rect.min.to_vec2());
+        ui.painter().rect_stroke(view.intersect(map_rect), 0.0, Stroke::new(1.0, Color32::YELLOW));
+    }
+
+    // Forget anything specific to the previous page
+    fn reset_page(&mut self) {
+        self.lines = Vec::new();
+        self.provenance = None;
+        self.crop_rect = Rect::NOTHING;
+        self.duplicates = None;
+        self.open_article = self.state.pages.get(&self.state.images[self.state.open_image])
+            .and_then(|page| page.open_article.filter(|&id| page.article_index(id).is_some()));
+    }
+
+    // Record the open article in the page, so it's saved and restored on returning to the page
+    fn remember_open_article(&mut self) {
+        if !self.state.images.is_empty() {
+            self.state.page().open_article = self.open_article;
+        }
+    }
+
+    fn image_bounds(&self) -> Rect {
+        Rect::from_min_max(Pos2::ZERO, Pos2::new(self.image.width() as f32, self.image.height() as f32))
+    }
+
+    fn in_image_bounds(&self, p: Pos2) -> bool {
+        self.image_bounds().contains(p)
+    }
+
+    // Polys might have come from a different resolution scan
+    fn find_out_of_bounds(&self) -> Vec<(u64, usize)> {
+        let mut found = Vec::new();
+        let Some(page) = self.state.pages.get(&self.state.images[self.state.open_image]) else {
+            return found;
+        };
+        for article in &page.articles {
+            for (i, vertexes) in article.polys.iter().enumerate() {
+                if !vertexes.iter().all(|&p| self.in_image_bounds(p)) {
+                    found.push((article.id, i));
+                }
             }
+        }
+        found
+    }
 
-            ui.allocate_ui_at_rect(
-                Rect::from_min_max(Pos2::new(viewport.x - 400.0, 0.0), viewport.to_pos2()),
-                |ui| {
-                    egui::Frame::none()
-                        .fill(egui::Color32::from_gray(192))
-                        .show(ui, |ui| {
-                            self.sidebar(scaler, ui);
-                        });
-                },
-            );
-        });
+    // Switch to another image, unless there's an extraction that hasn't been appended yet
+    fn goto_image(&mut self, index: usize) {
+        if !self.draft_text.is_empty() && !self.draft_appended {
+            self.pending_navigation = Some(index);
+            return;
+        }
+        self.remember_open_article();
+        self.state.open_image = index;
+        self.scrap_selection.clear();
+        self.load_image_async();
     }
-}
 
-impl MyApp {
-    fn popup(&mut self, ui: &mut egui::Ui) {
-        let draft_font = FontId::new(11.0, FontFamily::Monospace);
+    // Replace everything with a different project's annotations
+    fn open_project(&mut self, index: usize) {
+        self.remember_open_article();
+        self.projects.last_opened = index;
+        self.projects.save();
+        self.project = self.projects.projects[index].clone();
+        self.state = self.project.load_state();
+        self.saved = serde_yaml::to_string(&self.state).unwrap();
+        self.scale = self.project.scale;
+        self.offset = Vec2::ZERO;
+        self.vertexes.clear();
+        self.holes.clear();
+        self.draft_text.clear();
+        self.draft_page = None;
+        self.undo.clear();
+        self.preset_fill = None;
+        // These all refer to pages or articles of the old project
+        self.text_changes = None;
+        self.duplicates = None;
+        self.gremlins = None;
+        self.pending_navigation = None;
+        self.selected_poly = None;
+        if let Some(edit) = self.external_edit.take() {
+            edit.abandon();
+        }
+        self.blank_detection = None;
+        self.check_images();
+        self.load_image_async();
+    }
+
+    // Look for a misconfigured images folder, so we can explain it rather than showing black pages
+    fn check_images(&mut self) {
+        let dir = &self.project.images;
+        self.images_path_edit = dir.clone();
+        self.images_problem = if !std::path::Path::new(dir).is_dir() {
+            Some(format!("The images folder {} doesn't exist", dir))
+        } else if !self.state.images.is_empty() && !self.state.images.iter().any(|filename| std::path::Path::new(&format!("{}{}", dir, filename)).is_file()) {
+            Some(format!("None of the {} images listed in {} are in {}", self.state.images.len(), self.project.annotations, dir))
+        } else {
+            None
+        };
+    }
+
+    // Point the current project at a different images folder
+    fn set_images_path(&mut self, mut path: String) {
+        if !path.ends_with('/') {
+            path.push('/');
+        }
+        self.project.images = path.clone();
+        self.projects.projects[self.projects.last_opened].images = path;
+        self.projects.save();
+        self.check_images();
+        if self.images_problem.is_none() {
+            self.load_image();
+        }
+    }
+
+    fn save(&mut self) {
+        self.remember_open_article();
+        if self.state.settings.integer_polys {
+            self.state.round_polys();
+        }
+        let yaml = serde_yaml::to_string(&self.state).unwrap();
+        std::fs::write(&self.project.annotations, &yaml).unwrap();
+        self.saved = yaml;
+        log::info!("Saved {}", self.project.annotations);
+    }
+
+    // Whether the annotations have changed since they were loaded or saved
+    fn dirty(&mut self) -> bool {
+        self.remember_open_article();
+        serde_yaml::to_string(&self.state).unwrap() != self.saved
+    }
+
+    fn unappended_draft(&self) -> bool {
+        !self.draft_text.trim().is_empty() && !self.draft_appended
+    }
+
+    // The one way to switch projects or quit: go ahead if there's nothing to lose, or save
+    // automatically if that's enabled, or else ask first
+    fn leave(&mut self, ctx: &egui::Context, leave: Leave) {
+        // Serialising the whole state to compare is slow, so once, rather than every frame the dialog is open
+        let mut dirty = self.dirty();
+        if self.projects.auto_save && !self.readonly && dirty {
+            self.save();
+            dirty = false;
+        }
+        if dirty || self.unappended_draft() {
+            self.leaving = Some((leave, dirty));
+        } else {
+            self.finish_leaving(ctx, leave);
+        }
+    }
+
+    fn finish_leaving(&mut self, ctx: &egui::Context, leave: Leave) {
+        self.leaving = None;
+        match leave {
+            Leave::Project(index) => self.open_project(index),
+            Leave::Quit => {
+                self.quitting = true;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        }
+    }
+
+    // Remember the open page's articles and scratchpad before changing them, so the change can be undone
+    fn checkpoint(&mut self) {
+        self.checkpoint_page(self.state.images[self.state.open_image].clone());
+    }
+
+    // The same, for any page, when a bulk change edits pages that aren't open
+    fn checkpoint_page(&mut self, filename: String) {
+        let page = self.state.page_named(filename.clone());
+        let snapshot = Snapshot { articles: page.articles.clone(), scratchpad: page.scratchpad.clone() };
+        self.push_undo(filename, snapshot);
+    }
+
+    // The same, for a change to one article that's already been made
+    fn checkpoint_article(&mut self, before: Article) {
+        let page = self.state.page();
+        let mut articles = page.articles.clone();
+        if let Some(article) = articles.iter_mut().find(|article| article.id == before.id) {
+            *article = before;
+        }
+        let snapshot = Snapshot { articles, scratchpad: page.scratchpad.clone() };
+        self.push_undo(self.state.images[self.state.open_image].clone(), snapshot);
+    }
+
+    fn push_undo(&mut self, filename: String, snapshot: Snapshot) {
+        let stack = self.undo.entry(filename).or_default();
+        stack.push(snapshot);
+        if stack.len() > UNDO_LENGTH {
+            stack.remove(0);
+        }
+    }
+
+    // Put back the given page as it was before its most recent edit, whether or not it's open
+    fn undo(&mut self, filename: &str) {
+        let Some(snapshot) = self.undo.get_mut(filename).and_then(|stack| stack.pop()) else {
+            return;
+        };
+        let remaining = self.undo[filename].len();
+        if remaining == 0 {
+            self.undo.remove(filename);
+        }
+        let open = self.state.images.get(self.state.open_image).is_some_and(|open| open == filename);
+        let page = self.state.page_named(filename.to_owned());
+        page.articles = snapshot.articles;
+        page.scratchpad = snapshot.scratchpad;
+        if page.open_article.is_some_and(|id| page.article_index(id).is_none()) {
+            page.open_article = None;
+        }
+        if open && self.open_article.is_some_and(|id| self.state.page().article_index(id).is_none()) {
+            self.open_article = None;
+        }
+        self.editing_text = None;
+        self.status = format!("Undid an edit on {} ({} more can be undone there)", filename, remaining);
+    }
+
+    // Write the article's text to a temporary file and open it in $VISUAL or $EDITOR, which can be
+    // a command with arguments like "code --wait"
+    fn open_in_editor(&mut self, id: u64) {
+        if let Some(edit) = &self.external_edit {
+            self.status = format!("Article {} is already open in an external editor", edit.article);
+            return;
+        }
+        let Some(editor) = ["VISUAL", "EDITOR"].iter().filter_map(|var| std::env::var(var).ok()).find(|editor| !editor.trim().is_empty()) else {
+            self.status = "Set $VISUAL or $EDITOR to edit articles in an external editor".to_owned();
+            return;
+        };
+        let Some(text) = self.state.page().article_mut(id).map(|article| article.text.clone()) else {
+            return;
+        };
+        let path = std::env::temp_dir().join(format!("scrapbook-annotate-{}-{}.txt", std::process::id(), id));
+        if let Err(err) = std::fs::write(&path, &text) {
+            log::error!("Failed to write {}: {}", path.display(), err);
+            self.status = format!("Couldn't write {}: {}", path.display(), err);
+            return;
+        }
+        let mut words = editor.split_whitespace();
+        let program = words.next().unwrap();
+        match std::process::Command::new(program).args(words).arg(&path).spawn() {
+            Ok(child) => {
+                let modified = std::fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+                let page = self.state.images[self.state.open_image].clone();
+                self.external_edit = Some(ExternalEdit { page, article: id, path, child, text, modified });
+                self.status = format!("Editing article {} in {}; it's read back here whenever it's saved", id, editor);
+            }
+            Err(err) => {
+                log::error!("Failed to start {}: {}", editor, err);
+                self.status = format!("Couldn't start {}: {}", editor, err);
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+    }
+
+    // Read back the external editor's file if it's been saved since last time, and stop once the
+    // editor exits. If the article was deleted or changed here meanwhile, the editor's version is
+    // left in the file instead of overwriting anything
+    fn poll_external_edit(&mut self, ctx: &egui::Context) {
+        let Some(mut edit) = self.external_edit.take() else {
+            return;
+        };
+        let exited = match edit.child.try_wait() {
+            Ok(status) => status.is_some(),
+            Err(err) => {
+                log::error!("Failed to check on the external editor: {}", err);
+                true
+            }
+        };
+
+        let modified = std::fs::metadata(&edit.path).and_then(|metadata| metadata.modified()).ok();
+        if modified != edit.modified {
+            edit.modified = modified;
+            match std::fs::read_to_string(&edit.path) {
+                Ok(mut text) => {
+                    // Most editors end the file with a newline whether or not there was one
+                    if !edit.text.ends_with('\n') && text.ends_with('\n') {
+                        text.pop();
+                    }
+                    let current = self.state.pages.get(&edit.page)
+                        .and_then(|page| page.articles.iter().find(|article| article.id == edit.article))
+                        .cloned();
+                    match current {
+                        None => {
+                            self.status = format!("Article {} has been deleted, so its edited text was left in {}", edit.article, edit.path.display());
+                            edit.abandon();
+                            return;
+                        }
+                        Some(article) if article.text != edit.text => {
+                            self.status = format!("Article {} was changed here while it was open in the editor, so the editor's version was left in {}", edit.article, edit.path.display());
+                            edit.abandon();
+                            return;
+                        }
+                        Some(article) if article.text != text => {
+                            // The page mightn't be open, so this can't use checkpoint_article
+                            self.checkpoint_page(edit.page.clone());
+                            let page = self.state.page_named(edit.page.clone());
+                            let article = page.articles.iter_mut().find(|article| article.id == edit.article).unwrap();
+                            article.text = text.clone();
+                            article.touch();
+                            self.status = format!("Read back article {} from the external editor", edit.article);
+                        }
+                        Some(_) => {}
+                    }
+                    edit.text = text;
+                }
+                Err(err) => log::error!("Failed to read {}: {}", edit.path.display(), err),
+            }
+        }
+
+        if exited {
+            let _ = std::fs::remove_file(&edit.path);
+            self.status = format!("Finished editing article {} externally", edit.article);
+        } else {
+            ctx.request_repaint_after(std::time::Duration::from_millis(500));
+            self.external_edit = Some(edit);
+        }
+    }
+
+    fn new_article(&mut self) {
+        self.checkpoint();
+        let id = self.state.new_article_id();
+        let mut article = Article::new(id, String::new());
+        // Clippings on the same page are often from the same paper
+        article.source = self.state.page().articles.iter().rev().find_map(|article| article.source.clone());
+        self.state.page().articles.push(article);
+        self.open_article = Some(id);
+    }
+
+    fn append_draft(&mut self, join: Join) {
+        // The poly and the OCR only make sense on the page they came from
+        let open_page = &self.state.images[self.state.open_image];
+        if let Some(page) = self.draft_page.as_ref().filter(|&page| page != open_page) {
+            self.status = format!("The draft was extracted from {}, so it can't be appended on this page", page);
+            return;
+        }
+
+        if self.no_text_detected() && self.open_article.and_then(|id| self.state.page().article_mut(id)).is_some_and(|article| article.text.trim().is_empty()) {
+            self.status = "No text was detected, so the article would have no text. Save it as an image instead".to_owned();
+            return;
+        }
+        // Image articles only show their caption, so text appended to one would be lost
+        if self.open_article.and_then(|id| self.state.page().article_mut(id)).is_some_and(|article| article.kind == ArticleKind::Image) {
+            self.status = "The open article is an image, so text can't be appended to it".to_owned();
+            return;
+        }
+
+        let scrap = self.draft_scrap(&join);
+        let simplify = self.state.settings.simplify_polys;
+        let removed = self.vertexes.len() - scrap.vertexes.len();
+
+        let auto_verify = self.state.settings.auto_verify;
+        let confident = self.confident(&self.lines);
+
+        let Some(article) = self.open_article.and_then(|id| self.state.page().article_mut(id)) else {
+            return;
+        };
+        // Undoing the creation of an empty article is enough to undo this too
+        if !article.text.is_empty() || !article.polys.is_empty() {
+            self.checkpoint();
+        }
+        let article = self.state.page().article_mut(self.open_article.unwrap()).unwrap();
+        join.apply(&mut article.text);
+        // Stays verified only if everything appended so far was confident
+        if auto_verify {
+            article.verified = confident && (article.polys.is_empty() || article.verified);
+        }
+        article.append_scrap(scrap);
+        self.draft_appended = true;
+
+        if simplify {
+            self.status = format!("Simplification removed {} of {} vertexes", removed, self.vertexes.len());
+        }
+    }
+
+    // The draft as it would be appended: its text, its poly (simplified if that's enabled) and
+    // the OCR it came from
+    fn draft_scrap(&self, join: &Join) -> Scrap {
+        let vertexes = if self.state.settings.simplify_polys {
+            Self::simplify_poly(&self.vertexes, self.state.settings.simplify_tolerance)
+        } else {
+            self.vertexes.clone()
+        };
+        let raw_ocr = if self.state.settings.store_raw_ocr && !self.lines.is_empty() {
+            Some(RawOcr {
+                width: self.crop_image.width() as f32,
+                crop: Some(self.crop_rect),
+                paragraph: matches!(join, Join::Paragraph),
+                separator: if let Join::Custom(separator) = join { Some(separator.clone()) } else { None },
+                lines: self.lines.clone(),
+            })
+        } else {
+            None
+        };
+        let provenance = if self.lines.is_empty() { None } else { self.provenance.clone() };
+        Scrap { text: self.draft_text.trim_end().to_owned(), vertexes, holes: self.holes.clone(), raw_ocr, provenance }
+    }
+
+    // Put the draft aside on this page's scratchpad, to be made into articles later
+    fn draft_to_scratchpad(&mut self) {
+        let open_page = &self.state.images[self.state.open_image];
+        if let Some(page) = self.draft_page.as_ref().filter(|&page| page != open_page) {
+            self.status = format!("The draft was extracted from {}, so it can't go on this page's scratchpad", page);
+            return;
+        }
+        if self.vertexes.len() < 3 {
+            self.status = "There's no poly to put on the scratchpad".to_owned();
+            return;
+        }
+        self.checkpoint();
+        let scrap = self.draft_scrap(&Join::Line);
+        self.state.page().scratchpad.push(scrap);
+        self.draft_appended = true;
+        self.status = format!("{} extractions on the scratchpad", self.state.page().scratchpad.len());
+    }
+
+    // Make the selected scraps into a new article, or append them to the open one, in order
+    fn slice_scratchpad(&mut self, new_article: bool) {
+        if self.scrap_selection.is_empty() {
+            return;
+        }
+        self.checkpoint();
+        if new_article || self.open_article.and_then(|id| self.state.page().article_index(id)).is_none() {
+            let id = self.state.new_article_id();
+            self.state.page().articles.push(Article::new(id, String::new()));
+            self.open_article = Some(id);
+        }
+        let selection = std::mem::take(&mut self.scrap_selection);
+        let page = self.state.page();
+        let mut scraps = Vec::new();
+        for (i, scrap) in std::mem::take(&mut page.scratchpad).into_iter().enumerate() {
+            if selection.contains(&i) {
+                scraps.push(scrap);
+            } else {
+                page.scratchpad.push(scrap);
+            }
+        }
+        let confident = scraps.iter().all(|scrap| scrap.raw_ocr.as_ref().is_some_and(|raw_ocr| self.confident(&raw_ocr.lines)));
+        let auto_verify = self.state.settings.auto_verify;
+        let count = scraps.len();
+        let article = self.state.page().article_mut(self.open_article.unwrap()).unwrap();
+        if auto_verify {
+            article.verified = confident && (article.polys.is_empty() || article.verified);
+        }
+        for scrap in scraps {
+            article.append_scrap(scrap);
+        }
+        self.status = format!("Added {} extractions to the article", count);
+    }
+
+    fn scratchpad_ui(&mut self, ui: &mut egui::Ui) {
+        let readonly = self.readonly;
+        let mut remove = None;
+        let page = self.state.page();
+        if page.scratchpad.is_empty() {
+            ui.label("Put extractions here with \"Scratch\" to sort them into articles later");
+        }
+        for (i, scrap) in page.scratchpad.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                let mut selected = self.scrap_selection.contains(&i);
+                if ui.checkbox(&mut selected, format!("({})", i)).changed() {
+                    if selected {
+                        self.scrap_selection.insert(i);
+                    } else {
+                        self.scrap_selection.remove(&i);
+                    }
+                }
+                if ui.add_enabled(!readonly, egui::Button::new("-")).clicked() {
+                    remove = Some(i);
+                }
+            });
+            ui.add_enabled(!readonly, egui::TextEdit::multiline(&mut scrap.text).desired_rows(2));
+        }
+        if let Some(i) = remove {
+            self.checkpoint();
+            self.state.page().scratchpad.remove(i);
+            self.scrap_selection.clear();
+        }
+        ui.horizontal(|ui| {
+            let can_slice = !readonly && !self.scrap_selection.is_empty();
+            if ui.add_enabled(can_slice, egui::Button::new("New article")).on_hover_text("Make the selected extractions into a new article").clicked() {
+                self.slice_scratchpad(true);
+            }
+            let can_append = can_slice && self.open_article.is_some();
+            if ui.add_enabled(can_append, egui::Button::new("Append to open article")).clicked() {
+                self.slice_scratchpad(false);
+            }
+        });
+    }
+
+    // The selected poly's index, if it's in the open article
+    fn selected_poly(&self) -> Option<usize> {
+        self.selected_poly.filter(|&(id, _)| Some(id) == self.open_article).map(|(_, i)| i)
+    }
+
+    fn step_poly(&mut self, forwards: bool) {
+        let Some(id) = self.open_article else {
+            return;
+        };
+        let count = self.state.page().article_mut(id).map_or(0, |article| article.polys.len());
+        if count == 0 {
+            return;
+        }
+        let i = match self.selected_poly() {
+            Some(i) if forwards => (i + 1).min(count - 1),
+            Some(i) => i.saturating_sub(1),
+            None if forwards => 0,
+            None => count - 1,
+        };
+        self.selected_poly = Some((id, i));
+    }
+
+    fn delete_selected_poly(&mut self) {
+        let (Some(id), Some(i)) = (self.open_article, self.selected_poly()) else {
+            return;
+        };
+        if self.state.page().article_mut(id).is_none_or(|article| i >= article.polys.len()) {
+            return;
+        }
+        self.checkpoint();
+        let article = self.state.page().article_mut(id).unwrap();
+        article.remove_poly(i);
+        let remaining = article.polys.len();
+        // Stay in the same place in the list, so several can be deleted in a row
+        self.selected_poly = (remaining > 0).then_some((id, i.min(remaining - 1)));
+        self.status = format!("Deleted poly {}; {} left", i, remaining);
+    }
+
+    // Stop adding vertexes, so the poly is drawn closed as it'll be extracted
+    fn finish_poly(&mut self) {
+        self.drawing = false;
+        self.status = if self.vertexes.len() >= 3 {
+            format!("Finished the {}-point poly (E to extract)", self.vertexes.len())
+        } else {
+            "A poly needs at least 3 points".to_owned()
+        };
+    }
+
+    // Crop and OCR the current poly, or the last one extracted on this page if there isn't one,
+    // so a preprocessing setting can be tweaked and the same region extracted again
+    fn extract(&mut self) {
+        // The old page's image is still shown until the new one is decoded
+        if self.loading.is_some() {
+            self.status = "Wait for the page to load before extracting".to_owned();
+            return;
+        }
+        self.drawing = false;
+        if self.vertexes.len() < 3 {
+            let open_page = &self.state.images[self.state.open_image];
+            if let Some(extraction) = self.history.iter().find(|extraction| extraction.page == *open_page) {
+                self.vertexes = extraction.vertexes.clone();
+                self.holes = extraction.holes.clone();
+            }
+        }
+        match self.extract_image() {
+            Some(image) => {
+                (self.draft_text, self.lines) = self.runtime.block_on(self.extract_text(image));
+                self.provenance = Some(self.current_provenance(self.projects.ocr.backend.name()));
+                self.draft_page = Some(self.state.images[self.state.open_image].clone());
+                self.draft_appended = false;
+                self.draft_cursor = (0, 0);
+                self.record_extraction();
+                if self.no_text_detected() {
+                    self.status = "No text was detected in this region. If it's a picture, save it as an image instead".to_owned();
+                }
+            }
+            None => self.status = "The poly is too small to extract".to_owned(),
+        }
+    }
+
+    // OCR of the draft's region succeeded but found no lines, as with a photo or a blank area
+    fn no_text_detected(&self) -> bool {
+        self.draft_page.is_some() && !self.draft_appended && self.lines.is_empty() && self.draft_text.trim().is_empty()
+    }
+
+    // Add the poly as a new image article, without running OCR, for a photo or illustration. The
+    // crop is only rendered to check the poly, and to save it if that's enabled
+    fn save_as_image(&mut self) {
+        if self.loading.is_some() {
+            self.status = "Wait for the page to load before saving an image".to_owned();
+            return;
+        }
+        if self.extract_image().is_none() {
+            self.status = "The poly is too small to save as an image".to_owned();
+            return;
+        }
+        self.drawing = false;
+        self.new_article();
+        let id = self.open_article.unwrap();
+
+        let crop = if self.state.settings.save_image_crops {
+            match self.save_crop(id) {
+                Ok(path) => Some(path),
+                Err(err) => {
+                    log::error!("Failed to save the crop: {}", err);
+                    self.status = format!("Couldn't save the crop: {}", err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let vertexes = if self.state.settings.simplify_polys {
+            Self::simplify_poly(&self.vertexes, self.state.settings.simplify_tolerance)
+        } else {
+            self.vertexes.clone()
+        };
+        let holes = self.holes.clone();
+        let article = self.state.page().article_mut(id).unwrap();
+        article.kind = ArticleKind::Image;
+        article.crop = crop.clone();
+        article.push_poly(vertexes, holes, None, None);
+        // There's no draft to append, but it shouldn't be appended as text afterwards either
+        self.draft_appended = true;
+        if let Some(path) = crop {
+            self.status = format!("Added image article {}, saved as {}", id, path);
+        } else if !self.status.starts_with("Couldn't") {
+            self.status = format!("Added image article {}", id);
+        }
+    }
+
+    // Write the masked crop without the levels, since it's for looking at rather than OCR, to a
+    // folder next to the annotations file
+    fn save_crop(&self, id: u64) -> Result<String, String> {
+        // In the same subfolders as the page, so pages with the same name in different folders don't clash
+        let page = std::path::Path::new(&self.state.images[self.state.open_image]);
+        let stem = page.file_stem().map_or_else(|| "page".into(), |stem| stem.to_string_lossy());
+        let path = std::path::Path::new(&self.project.export_path("-images")).join(page).with_file_name(format!("{}-{}.png", stem, id));
+        std::fs::create_dir_all(path.parent().unwrap()).map_err(|err| err.to_string())?;
+        let path = path.to_string_lossy().into_owned();
+        let mut image = RgbImage::new(0, 0);
+        Self::render_crop(&self.image, self.crop_rect, &self.crop_mask, (0, 255), self.mask_fill(), &mut image);
+        image.save(&path).map_err(|err| err.to_string())?;
+        Ok(path)
+    }
+
+    fn copy_poly(&mut self, ctx: &egui::Context, poly: CopiedPoly) {
+        ctx.copy_text(serde_json::to_string(&poly).unwrap());
+        self.status = format!("Copied a poly with {} vertexes", poly.vertexes.len());
+        self.copied_poly = Some(poly);
+    }
+
+    // Replace the current poly with a copied one, so it can be extracted and appended as if it
+    // had been drawn here
+    fn paste_poly(&mut self, poly: CopiedPoly) {
+        self.previous_vertexes = Some((std::mem::take(&mut self.vertexes), std::mem::take(&mut self.holes)));
+        self.status = format!("Pasted a poly with {} vertexes", poly.vertexes.len());
+        self.vertexes = poly.vertexes;
+        self.holes = poly.holes;
+    }
+
+    // The settings that affect a crop's OCR, as they are now
+    fn current_provenance(&self, backend: &str) -> Provenance {
+        let settings = &self.state.settings;
+        let [r, g, b] = self.mask_fill();
+        let mut summary = format!("levels {}-{}, mask fill #{:02x}{:02x}{:02x}", self.levels.0, self.levels.1, r, g, b);
+        let direction = if settings.right_to_left { " right to left" } else { "" };
+        if settings.row_clustering {
+            summary += &format!(", rows within {} line heights{}", settings.row_threshold, direction);
+        } else if settings.line_sort_fudge {
+            summary += &format!(", line sort divisor {}{}", settings.line_sort_divisor, direction);
+        }
+        summary += &format!(", paragraph indent {}-{}px", settings.paragraph_indent_min, settings.paragraph_indent_max);
+        if settings.nonzero_fill {
+            summary += ", nonzero fill";
+        }
+        if settings.antialias_mask {
+            summary += ", anti-aliased mask";
+        }
+        Provenance { backend: backend.to_owned(), settings: summary, at: unix_time() }
+    }
+
+    // Fast entry: append the draft as N does, to the open article or a new one if no text article
+    // is open, then save and go to the next page
+    fn save_and_advance(&mut self) {
+        // Stay on the page rather than lose the region, with the status saying why
+        if self.no_text_detected() {
+            self.status = "No text was detected. Save it as an image, or press Page Down to skip it".to_owned();
+            return;
+        }
+        if !self.draft_text.trim().is_empty() && !self.draft_appended {
+            self.append_to_new_article();
+            if !self.draft_appended {
+                return;
+            }
+        }
+        self.save();
+        self.vertexes.clear();
+        self.holes.clear();
+        match self.step_image(true) {
+            Some(next) => self.goto_image(next),
+            None => self.status = "Saved; this is the last page".to_owned(),
+        }
+    }
+
+    // In place of the popup, for simple pages: the draft full width, with one key to move on
+    fn fast_entry_panel(&mut self, ui: &mut egui::Ui) {
+        let draft_font = FontId::new(11.0, FontFamily::Monospace);
+        egui::Frame::none()
+            .fill(egui::Color32::BLACK)
+            .inner_margin(4.0)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Drag a box around the clipping to extract it");
+                    if ui.add_enabled(self.vertexes.len() >= 3, egui::Button::new("Extract again (E)")).clicked() {
+                        self.extract();
+                    }
+                    if ui.button("Save and next (Ctrl+Enter)").clicked() {
+                        self.save_and_advance();
+                    }
+                });
+                egui::ScrollArea::vertical().max_height(FAST_ENTRY_HEIGHT - 40.0).show(ui, |ui| {
+                    ui.add(egui::TextEdit::multiline(&mut self.draft_text).font(draft_font).desired_width(f32::INFINITY).desired_rows(12));
+                });
+            });
+    }
+
+    // Append the draft to some article other than the open one, which opens it
+    fn append_to_article(&mut self, id: u64) {
+        self.choosing_append_target = false;
+        self.open_article = Some(id);
+        self.append_draft(Join::Line);
+        if let Some(i) = self.state.page().article_index(id).filter(|_| self.draft_appended) {
+            self.status = format!("Appended to article ({})", i);
+        }
+    }
+
+    // Append the draft to the open article, starting a new one first if none is open (or the open
+    // one is an image)
+    fn append_to_new_article(&mut self) {
+        if self.no_text_detected() {
+            self.status = "No text was detected, so there's nothing to append. Save it as an image instead".to_owned();
+            return;
+        }
+        if self.draft_text.is_empty() || self.draft_appended {
+            return;
+        }
+        let open = self.open_article.and_then(|id| self.state.page().article_mut(id)).map(|article| article.kind);
+        if open != Some(ArticleKind::Text) {
+            self.new_article();
+        }
+        self.append_draft(Join::Line);
+    }
+
+    // Red for low confidence through to green for high
+    fn confidence_color(confidence: f32) -> Color32 {
+        let t = ((confidence - LOW_CONFIDENCE) / (100.0 - LOW_CONFIDENCE)).clamp(0.0, 1.0);
+        Color32::from_rgb((255.0 * (1.0 - t)) as u8, (255.0 * t) as u8, 0)
+    }
+
+    // Whether OCR output is good enough to count as verified without proofreading
+    fn confident(&self, lines: &[Line]) -> bool {
+        self.state.settings.auto_verify
+            && !lines.is_empty()
+            && lines.iter().all(|line| line.confidence >= self.state.settings.auto_verify_confidence)
+    }
+
+    // Distance from p to the line segment a--b
+    fn segment_distance(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+        let ab = b - a;
+        let len_sq = ab.length_sq();
+        if len_sq == 0.0 {
+            return (p - a).length();
+        }
+        let t = ((p - a).dot(ab) / len_sq).clamp(0.0, 1.0);
+        (p - (a + ab * t)).length()
+    }
+
+    // Douglas-Peucker simplification of the open polyline, keeping both endpoints
+    fn simplify_polyline(points: &[Pos2], tolerance: f32, out: &mut Vec<Pos2>) {
+        let first = points[0];
+        let last = points[points.len() - 1];
+        let farthest = (1..points.len() - 1)
+            .map(|i| (i, Self::segment_distance(points[i], first, last)))
+            .max_by(|a, b| cmp_f32(&a.1, &b.1));
+        match farthest {
+            Some((i, dist)) if dist > tolerance => {
+                Self::simplify_polyline(&points[..=i], tolerance, out);
+                out.pop(); // don't duplicate the shared point
+                Self::simplify_polyline(&points[i..], tolerance, out);
+            }
+            _ => {
+                out.push(first);
+                out.push(last);
+            }
+        }
+    }
+
+    // Simplify a closed polygon, by splitting it into two polylines at the vertex farthest from the first
+    fn simplify_poly(vertexes: &[Pos2], tolerance: f32) -> Vec<Pos2> {
+        if vertexes.len() <= 3 {
+            return vertexes.to_vec();
+        }
+        let split = (1..vertexes.len())
+            .max_by(|&a, &b| cmp_f32(&(vertexes[a] - vertexes[0]).length(), &(vertexes[b] - vertexes[0]).length()))
+            .unwrap();
+
+        let mut ring = vertexes.to_vec();
+        ring.push(vertexes[0]);
+
+        let mut out = Vec::new();
+        Self::simplify_polyline(&ring[..=split], tolerance, &mut out);
+        out.pop();
+        Self::simplify_polyline(&ring[split..], tolerance, &mut out);
+        out.pop(); // the closing point duplicates the first
+
+        if out.len() < 3 {
+            return vertexes.to_vec();
+        }
+        out
+    }
+
+    fn merge_lines(lines: Vec<Line>, image_width: f32, settings: &Settings) -> String {
+        Self::merge_lines_with_offsets(&lines, image_width, settings).0
+    }
+
+    // Also returns where each line's text ended up in the merged text, as byte ranges
+    fn merge_lines_with_offsets(lines: &[Line], image_width: f32, settings: &Settings) -> (String, Vec<std::ops::Range<usize>>) {
+        let mut text = String::new();
+        let mut offsets = Vec::new();
+
+        let mut dehyphenating = false;
+        for (i, line) in lines.iter().enumerate() {
+            let mut start = 0;
+            let mut offset = text.len();
+            if dehyphenating {
+                // Add the first word after a hyphen onto the previous line
+                if let Some(space) = line.text.find(" ") {
+                    text.push_str(&line.text[0..space]);
+                    text.push('\n');
+                    start = space + 1;
+                }
+            } else {
+                // Try to detect paragraph indents
+                let mut indented = false;
+                if i > 0 && i + 1 < lines.len() {
+                    let x0 = lines[i - 1].left * image_width;
+                    let x1 = lines[i].left * image_width;
+                    let x2 = lines[i + 1].left * image_width;
+                    let min = settings.paragraph_indent_min;
+                    let max = settings.paragraph_indent_max;
+                    indented = min < x1 - x0 && x1 - x0 < max && min < x1 - x2 && x1 - x2 < max;
+                }
+                if i > 0 && line.paragraph.unwrap_or(indented) {
+                    text.push('\n');
+                }
+                offset = text.len();
+            }
+            if line.text.ends_with("-") {
+                text.push_str(&line.text[start..line.text.len() - 1]);
+                offsets.push(offset..text.len());
+                dehyphenating = true;
+            } else {
+                text.push_str(&line.text[start..]);
+                offsets.push(offset..text.len());
+                text.push('\n');
+                dehyphenating = false;
+            }
+        }
+
+        (text, offsets)
+    }
+
+    // Image-space boxes for the parts of the lines covered by the selected byte range of their merged
+    // text, dividing each line's bbox in proportion to its text since we don't have word geometry
+    fn selection_rects(lines: &[Line], offsets: &[std::ops::Range<usize>], crop: Rect, base: usize, selection: &std::ops::Range<usize>) -> Vec<Rect> {
+        let mut rects = Vec::new();
+        for (line, range) in lines.iter().zip(offsets) {
+            let (start, end) = (range.start + base, range.end + base);
+            let (a, b) = (selection.start.max(start), selection.end.min(end));
+            if a >= b {
+                continue;
+            }
+            let len = (end - start) as f32;
+            let bbox = line.bbox;
+            let x0 = bbox.left() + bbox.width() * (a - start) as f32 / len;
+            let x1 = bbox.left() + bbox.width() * (b - start) as f32 / len;
+            let to_image = |x: f32, y: f32| crop.min + Vec2::new(x, y) * crop.size();
+            rects.push(Rect::from_min_max(to_image(x0, bbox.top()), to_image(x1, bbox.bottom())));
+        }
+        rects
+    }
+
+    // The selected part of an editor's text as a byte range, while it has focus
+    fn selected_range(text: &str, output: &egui::text_edit::TextEditOutput) -> Option<std::ops::Range<usize>> {
+        if !output.response.has_focus() {
+            return None;
+        }
+        let [start, end] = output.cursor_range?.sorted_cursors();
+        let byte = |i: usize| text.char_indices().nth(i).map_or(text.len(), |(b, _)| b);
+        let range = byte(start.ccursor.index)..byte(end.ccursor.index);
+        (!range.is_empty()).then_some(range)
+    }
+
+    // The run of consecutive lines around the tallest one that are much taller than the rest, using
+    // bbox height as a proxy for font size. Needs a few lines to know what the body text looks like
+    fn detect_headline(lines: &[Line]) -> Option<std::ops::Range<usize>> {
+        if lines.len() < 3 {
+            return None;
+        }
+        let mut heights: Vec<f32> = lines.iter().map(|line| line.bbox.height()).collect();
+        heights.sort_by(cmp_f32);
+        let threshold = heights[heights.len() / 2] * HEADLINE_RATIO;
+
+        let tallest = (0..lines.len()).max_by(|&a, &b| cmp_f32(&lines[a].bbox.height(), &lines[b].bbox.height()))?;
+        if lines[tallest].bbox.height() < threshold {
+            return None;
+        }
+        let is_tall = |i: usize| lines[i].bbox.height() >= threshold;
+        let start = (0..tallest).rev().take_while(|&i| is_tall(i)).last().unwrap_or(tallest);
+        let end = (tallest..lines.len()).take_while(|&i| is_tall(i)).last().unwrap() + 1;
+        Some(start..end)
+    }
+
+    // Merge the lines with the headline as a single "# " line
+    fn merge_with_headline(&self, headline: std::ops::Range<usize>) -> String {
+        let width = self.crop_image.width() as f32;
+        let settings = &self.state.settings;
+        let mut text = Self::merge_lines(self.lines[..headline.start].to_vec(), width, settings);
+        let words: Vec<&str> = self.lines[headline.clone()].iter().map(|line| line.text.trim()).collect();
+        text += &format!("# {}\n", words.join(" "));
+        text += &Self::merge_lines(self.lines[headline.end..].to_vec(), width, settings);
+        text
+    }
+
+    // Split at the space nearest the middle, dividing the bbox in proportion to the text
+    fn split_line(line: &Line) -> Option<(Line, Line)> {
+        let middle = line.text.len() / 2;
+        let space = line.text.match_indices(' ').map(|(i, _)| i).min_by_key(|&i| i.abs_diff(middle))?;
+        let split_x = line.bbox.left() + line.bbox.width() * space as f32 / line.text.len() as f32;
+
+        let mut first = line.clone();
+        first.text = line.text[..space].to_owned();
+        first.bbox.max.x = split_x;
+        first.mid.x = first.bbox.center().x;
+
+        let mut second = line.clone();
+        second.text = line.text[space + 1..].to_owned();
+        second.bbox.min.x = split_x;
+        second.left = split_x;
+        second.mid.x = second.bbox.center().x;
+        second.paragraph = None;
+
+        Some((first, second))
+    }
+
+    // Textract sometimes splits one printed line into several side by side. Join consecutive
+    // (sorted) lines that overlap vertically and are close together horizontally, in reading order.
+    // Lines' coordinates are fractions of the crop, so crop_size converts them into pixels
+    fn join_split_lines(lines: Vec<Line>, crop_size: Vec2, settings: &Settings) -> Vec<Line> {
+        if !settings.join_split_lines {
+            return lines;
+        }
+        let mut joined: Vec<Line> = Vec::with_capacity(lines.len());
+        for line in lines {
+            if let Some(previous) = joined.last_mut() {
+                let (a, b) = (previous.bbox, line.bbox);
+                let overlap = (a.bottom().min(b.bottom()) - a.top().max(b.top())) * crop_size.y;
+                let height = a.height().min(b.height()) * crop_size.y;
+                // From the end of the previous line to the start of this one, in the reading direction
+                let gap = if settings.right_to_left { a.left() - b.right() } else { b.left() - a.right() } * crop_size.x;
+                if overlap >= height * SPLIT_LINE_OVERLAP && -height < gap && gap < height * SPLIT_LINE_GAP {
+                    let (chars_a, chars_b) = (previous.text.chars().count() as f32, line.text.chars().count() as f32);
+                    previous.confidence = (previous.confidence * chars_a + line.confidence * chars_b) / (chars_a + chars_b).max(1.0);
+                    previous.text = format!("{} {}", previous.text.trim_end(), line.text.trim_start());
+                    previous.bbox = a.union(b);
+                    previous.points = [previous.bbox.left_top(), previous.bbox.right_top(), previous.bbox.right_bottom(), previous.bbox.left_bottom()]
+                        .iter().map(|p| p.to_vec2()).collect();
+                    previous.left = previous.bbox.left();
+                    previous.mid = previous.bbox.center().to_vec2();
+                    continue;
+                }
+            }
+            joined.push(line);
+        }
+        joined
+    }
+
+    fn point_in_polys(p: Pos2, polys: &[Vec<Pos2>]) -> bool {
+        polys.iter().any(|vertexes| geometry::point_in_polygon(p, vertexes))
+    }
+
+    // Jaccard index of the sets of words, ignoring case and punctuation
+    fn text_similarity(a: &str, b: &str) -> f32 {
+        let words = |s: &str| -> BTreeSet<String> {
+            s.split_whitespace()
+                .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+                .filter(|w| !w.is_empty())
+                .collect()
+        };
+        let (a, b) = (words(a), words(b));
+        let union = a.union(&b).count();
+        if union == 0 {
+            return 0.0;
+        }
+        a.intersection(&b).count() as f32 / union as f32
+    }
+
+    // Approximate intersection-over-union of two articles' regions, by sampling on a grid
+    fn poly_overlap(a: &[Vec<Pos2>], b: &[Vec<Pos2>]) -> f32 {
+        if a.is_empty() || b.is_empty() {
+            return 0.0;
+        }
+        let bbox_a = Rect::from_points(&a.concat());
+        let bbox_b = Rect::from_points(&b.concat());
+        if !bbox_a.intersects(bbox_b) {
+            return 0.0;
+        }
+        let bbox = bbox_a.union(bbox_b);
+
+        let samples = 64;
+        let mut both = 0;
+        let mut either = 0;
+        for y in 0..samples {
+            for x in 0..samples {
+                let p = bbox.min + bbox.size() * Vec2::new((x as f32 + 0.5) / samples as f32, (y as f32 + 0.5) / samples as f32);
+                let in_a = Self::point_in_polys(p, a);
+                let in_b = Self::point_in_polys(p, b);
+                if in_a && in_b {
+                    both += 1;
+                }
+                if in_a || in_b {
+                    either += 1;
+                }
+            }
+        }
+        if either == 0 {
+            return 0.0;
+        }
+        both as f32 / either as f32
+    }
+
+    // Ear-clipping triangulation of a simple polygon, returning indexes into vertexes.
+    // Self-intersecting polygons won't triangulate properly, so we give up and use a fan for whatever is left
+    fn triangulate(vertexes: &[Pos2]) -> Vec<[usize; 3]> {
+        let cross = |o: Pos2, a: Pos2, b: Pos2| (a - o).x * (b - o).y - (a - o).y * (b - o).x;
+        let in_triangle = |p: Pos2, a: Pos2, b: Pos2, c: Pos2| {
+            let (d0, d1, d2) = (cross(a, b, p), cross(b, c, p), cross(c, a, p));
+            (d0 > 0.0 && d1 > 0.0 && d2 > 0.0) || (d0 < 0.0 && d1 < 0.0 && d2 < 0.0)
+        };
+
+        let n = vertexes.len();
+        let area: f32 = (0..n).map(|i| cross(Pos2::ZERO, vertexes[i], vertexes[(i + 1) % n])).sum();
+
+        let mut remaining: Vec<usize> = (0..n).collect();
+        let mut triangles = Vec::new();
+        while remaining.len() > 3 {
+            let m = remaining.len();
+            let ear = (0..m).find(|&i| {
+                let (ia, ib, ic) = (remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]);
+                let (a, b, c) = (vertexes[ia], vertexes[ib], vertexes[ic]);
+                // Must be a convex corner, and not contain any other vertex
+                cross(a, b, c) * area > 0.0
+                    && !remaining.iter().any(|&j| j != ia && j != ib && j != ic && in_triangle(vertexes[j], a, b, c))
+            });
+            let Some(i) = ear else {
+                break;
+            };
+            triangles.push([remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]]);
+            remaining.remove(i);
+        }
+        for i in 1..remaining.len().saturating_sub(1) {
+            triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+        }
+        triangles
+    }
+
+    // Area-weighted centroid of an article's polys, falling back to the mean vertex for degenerate polys
+    fn article_centroid(polys: &[Vec<Pos2>]) -> Option<Pos2> {
+        let mut area = 0.0;
+        let mut moment = Vec2::ZERO;
+        for vertexes in polys {
+            for i in 0..vertexes.len() {
+                let a = vertexes[i].to_vec2();
+                let b = vertexes[(i + 1) % vertexes.len()].to_vec2();
+                let cross = a.x * b.y - b.x * a.y;
+                area += cross / 2.0;
+                moment += (a + b) * cross / 6.0;
+            }
+        }
+        if area.abs() > 1.0 {
+            return Some((moment / area).to_pos2());
+        }
+        let points = polys.concat();
+        if points.is_empty() {
+            return None;
+        }
+        Some((points.iter().fold(Vec2::ZERO, |sum, p| sum + p.to_vec2()) / points.len() as f32).to_pos2())
+    }
+
+    // Find articles whose text would change if re-merged from their stored OCR
+    fn find_remerges(&self, all_pages: bool) -> Vec<TextChange> {
+        let open = &self.state.images[self.state.open_image];
+        let mut remerges = Vec::new();
+        for (filename, page) in &self.state.pages {
+            if !all_pages && filename != open {
+                continue;
+            }
+            for article in &page.articles {
+                if let Some(new) = article.remerge(&self.state.settings) {
+                    if new != article.text {
+                        remerges.push(TextChange { page: filename.clone(), article: article.id, old: article.text.clone(), new, apply: true });
+                    }
+                }
+            }
+        }
+        remerges
+    }
+
+    fn find_duplicates(&mut self) -> Vec<Duplicate> {
+        let articles = &self.state.page().articles;
+        let mut duplicates = Vec::new();
+        for a in 0..articles.len() {
+            for b in (a + 1)..articles.len() {
+                let text_similarity = Self::text_similarity(&articles[a].text, &articles[b].text);
+                let overlap = Self::poly_overlap(&articles[a].polys, &articles[b].polys);
+                if text_similarity >= DUPLICATE_TEXT_THRESHOLD || overlap >= DUPLICATE_OVERLAP_THRESHOLD {
+                    duplicates.push(Duplicate { a: articles[a].id, b: articles[b].id, text_similarity, overlap });
+                }
+            }
+        }
+        duplicates
+    }
+
+    fn poly_area(vertexes: &[Pos2]) -> f32 {
+        let n = vertexes.len();
+        let twice_area: f32 = (0..n).map(|i| {
+            let (a, b) = (vertexes[i], vertexes[(i + 1) % n]);
+            a.x * b.y - b.x * a.y
+        }).sum();
+        twice_area.abs() / 2.0
+    }
+
+    // Returns None if the poly is degenerate, or entirely outside the image
+    fn extract_image(&mut self) -> Option<Vec<u8>> {
+        // The signed area of a figure-eight's loops cancel out, though filling by winding covers
+        // both, so then the check waits for the mask
+        let nonzero = self.state.settings.nonzero_fill;
+        if self.vertexes.len() < 3 || (!nonzero && Self::poly_area(&self.vertexes) < MIN_CROP_AREA) {
+            return None;
+        }
+
+        let x0 = self.vertexes.iter().map(|p| p.x).min_by(cmp_f32).unwrap();
+        let x1 = self.vertexes.iter().map(|p| p.x).max_by(cmp_f32).unwrap();
+        let y0 = self.vertexes.iter().map(|p| p.y).min_by(cmp_f32).unwrap();
+        let y1 = self.vertexes.iter().map(|p| p.y).max_by(cmp_f32).unwrap();
+
+        let margin = 4.0;
+        let x0 = ((x0 - margin) as i32).clamp(0, self.image.width() as i32) as u32;
+        let x1 = ((x1 + margin) as i32).clamp(0, self.image.width() as i32) as u32;
+        let y0 = ((y0 - margin) as i32).clamp(0, self.image.height() as i32) as u32;
+        let y1 = ((y1 + margin) as i32).clamp(0, self.image.height() as i32) as u32;
+        if x1 <= x0 || y1 <= y0 {
+            return None;
+        }
+        self.crop_rect = Rect::from_min_max(Pos2::new(x0 as f32, y0 as f32), Pos2::new(x1 as f32, y1 as f32));
+
+        // Even-odd filling of all the rings together, so the holes get masked out, unless
+        // filling by winding
+        let antialias = self.state.settings.antialias_mask;
+        let origin = Vec2::new(x0 as f32, y0 as f32);
+        let rings: Vec<Vec<Pos2>> = std::iter::once(&self.vertexes).chain(&self.holes)
+            .map(|ring| ring.iter().map(|&p| p - origin).collect())
+            .collect();
+
+        self.crop_mask.clear();
+        self.histogram.clear();
+        self.histogram.resize(256, 0);
+        for y in 0..(y1 - y0) {
+            for x in 0..(x1 - x0) {
+                let inside = |p| if nonzero { geometry::point_in_rings_nonzero(p, &rings) } else { geometry::point_in_rings(p, &rings) };
+                let coverage = if antialias {
+                    geometry::coverage(x, y, MASK_SAMPLES, inside)
+                } else if inside(Pos2::new(x as f32, y as f32)) {
+                    255
+                } else {
+                    0
+                };
+                if coverage >= 128 {
+                    let p = self.image.get_pixel(x0 + x, y0 + y);
+                    self.histogram[Self::luma(p) as usize] += 1;
+                }
+                self.crop_mask.push(coverage);
+            }
+        }
+        if nonzero && (self.histogram.iter().sum::<u32>() as f32) < MIN_CROP_AREA {
+            return None;
+        }
+
+        self.apply_levels();
+
+        let source = image::imageops::crop_imm(&self.image, x0, y0, x1 - x0, y1 - y0).to_image();
+        let egui_image = ColorImage::from_rgb([source.width() as _, source.height() as _], source.as_flat_samples().as_slice());
+        self.retained_source_crop = RetainedImage::from_color_image("source crop", egui_image);
+
+        // The bytes are handed over to the OCR request, but they can at least start out big enough
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.jpeg_size);
+        self.crop_image.write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(&mut Cursor::new(&mut bytes), 90)).unwrap();
+        self.jpeg_size = bytes.len();
+
+        Some(bytes)
+    }
+
+    fn luma(p: &image::Rgb<u8>) -> u8 {
+        ((p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114) / 1000) as u8
+    }
+
+    // Rebuild crop_image from the source image, masked by crop_mask and with the levels applied
+    fn apply_levels(&mut self) {
+        Self::render_crop(&self.image, self.crop_rect, &self.crop_mask, self.levels, self.mask_fill(), &mut self.crop_image);
+        let image = &self.crop_image;
+        let egui_image = ColorImage::from_rgb([image.width() as _, image.height() as _], image.as_flat_samples().as_slice());
+        self.retained_crop = RetainedImage::from_color_image("crop", egui_image);
+    }
+
+    // Write the masked, levels-adjusted crop into out, reusing its buffer unless the crop is bigger
+    // than anything it's held before
+    fn render_crop(image: &RgbImage, crop_rect: Rect, mask: &[u8], levels: (u8, u8), mask_fill: [u8; 3], out: &mut RgbImage) {
+        // Levels from a hand-edited preset can be anything, and white must stay above black
+        let black = levels.0.min(254);
+        let (black, white) = (black as f32, levels.1.max(black + 1) as f32);
+        let x0 = crop_rect.min.x as u32;
+        let y0 = crop_rect.min.y as u32;
+        let (width, height) = (crop_rect.width() as u32, crop_rect.height() as u32);
+        let mut buffer = std::mem::replace(out, RgbImage::new(0, 0)).into_raw();
+        buffer.resize((width * height * 3) as usize, 0);
+        *out = RgbImage::from_raw(width, height, buffer).unwrap();
+
+        for (x, y, p) in out.enumerate_pixels_mut() {
+            let coverage = mask[(y * width + x) as usize];
+            if coverage == 0 {
+                *p = image::Rgb(mask_fill);
+                continue;
+            }
+            let source = image.get_pixel(x0 + x, y0 + y);
+            let levelled = source.0.map(|c| ((c as f32 - black) * 255.0 / (white - black)).clamp(0.0, 255.0));
+            // Edge pixels are blended toward the fill, by how much of them is outside
+            let alpha = coverage as f32 / 255.0;
+            *p = image::Rgb(std::array::from_fn(|i| (mask_fill[i] as f32 + (levelled[i] - mask_fill[i] as f32) * alpha).round() as u8));
+        }
+    }
+
+    // Trying a preset doesn't change the saved mask fill, just as it doesn't change any saved levels
+    fn mask_fill(&self) -> [u8; 3] {
+        self.preset_fill.unwrap_or(self.state.settings.mask_fill)
+    }
+
+    // The preset the current preprocessing matches, if any
+    fn current_preset(&self) -> Option<usize> {
+        self.state.settings.presets.iter().position(|preset| preset.levels == self.levels && preset.mask_fill == self.mask_fill())
+    }
+
+    // Switch to the next or previous preset and extract the same region again, to compare the results
+    fn cycle_preset(&mut self, forwards: bool) {
+        let count = self.state.settings.presets.len();
+        if count == 0 {
+            self.status = "There are no presets".to_owned();
+            return;
+        }
+        let index = match self.current_preset() {
+            Some(i) if forwards => (i + 1) % count,
+            Some(i) => (i + count - 1) % count,
+            None => 0,
+        };
+        let preset = self.state.settings.presets[index].clone();
+        self.levels = preset.levels;
+        self.preset_fill = Some(preset.mask_fill);
+        self.status = format!("Preset {} of {}: {}", index + 1, count, preset.name);
+        self.extract();
+    }
+
+    fn presets_ui(&mut self, ui: &mut egui::Ui) {
+        let current = self.current_preset();
+        let mut selected = current;
+        ui.horizontal(|ui| {
+            let name = current.map_or("Custom", |i| self.state.settings.presets[i].name.as_str()).to_owned();
+            egui::ComboBox::from_id_salt("preset")
+                .selected_text(name)
+                .show_ui(ui, |ui| {
+                    for (i, preset) in self.state.settings.presets.iter().enumerate() {
+                        ui.selectable_value(&mut selected, Some(i), &preset.name);
+                    }
+                });
+            if ui.add_enabled(!self.readonly, egui::Button::new("Next (P)")).on_hover_text("Extract again with the next preset (Shift+P for the previous one)").clicked() {
+                self.cycle_preset(true);
+            }
+            if ui.add_enabled(!self.readonly && current.is_some(), egui::Button::new("Delete")).clicked() {
+                self.state.settings.presets.remove(current.unwrap());
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.new_preset).desired_width(120.0).hint_text("preset name"));
+            let name = self.new_preset.trim().to_owned();
+            if ui.add_enabled(!self.readonly && !name.is_empty() && current.is_none(), egui::Button::new("Save preset")).clicked() {
+                self.state.settings.presets.push(Preset { name, levels: self.levels, mask_fill: self.mask_fill() });
+                self.new_preset.clear();
+            }
+        });
+        if selected != current {
+            let preset = &self.state.settings.presets[selected.unwrap()];
+            self.levels = preset.levels;
+            self.preset_fill = Some(preset.mask_fill);
+            self.apply_levels();
+        }
+    }
+
+    // Histogram of the crop, with draggable black and white points
+    fn levels_ui(&mut self, ui: &mut egui::Ui) {
+        let (response, painter) = ui.allocate_painter(Vec2::new(256.0, 60.0), Sense::click_and_drag());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, Color32::from_gray(24));
+
+        let max = self.histogram.iter().copied().max().unwrap_or(0).max(1) as f32;
+        for (i, &count) in self.histogram.iter().enumerate() {
+            let x = rect.left() + i as f32 + 0.5;
+            let height = rect.height() * (count as f32 / max).sqrt();
+            painter.line_segment([Pos2::new(x, rect.bottom()), Pos2::new(x, rect.bottom() - height)], Stroke::new(1.0, Color32::GRAY));
+        }
+
+        let shade = Color32::from_black_alpha(160);
+        painter.rect_filled(Rect::from_min_max(rect.min, Pos2::new(rect.left() + self.levels.0 as f32, rect.bottom())), 0.0, shade);
+        painter.rect_filled(Rect::from_min_max(Pos2::new(rect.left() + self.levels.1 as f32 + 1.0, rect.top()), rect.max), 0.0, shade);
+        for level in [self.levels.0, self.levels.1] {
+            let x = rect.left() + level as f32 + 0.5;
+            painter.line_segment([Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())], Stroke::new(1.0, Color32::RED));
+        }
+
+        let mut changed = false;
+        if let Some(pos) = response.interact_pointer_pos() {
+            let level = (pos.x - rect.left()).clamp(0.0, 255.0) as u8;
+            // Move whichever point is nearer
+            if level.abs_diff(self.levels.0) <= level.abs_diff(self.levels.1) {
+                self.levels.0 = level.min(self.levels.1.saturating_sub(1));
+            } else {
+                self.levels.1 = level.max(self.levels.0.saturating_add(1));
+            }
+            changed = true;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Levels {}-{}", self.levels.0, self.levels.1));
+            if ui.button("Reset").clicked() {
+                self.levels = (0, 255);
+                changed = true;
+            }
+        });
+
+        if changed {
+            self.apply_levels();
+        }
+    }
+
+    async fn extract_text(&self, image_bytes: Vec<u8>) -> (String, Vec<Line>) {
+        match Self::detect_lines(image_bytes, &self.projects.ocr).await {
+            Ok(mut lines) => {
+                Self::sort_lines(&mut lines, &self.state.settings);
+                let crop_size = Vec2::new(self.crop_image.width() as f32, self.crop_image.height() as f32);
+                let lines = Self::join_split_lines(lines, crop_size, &self.state.settings);
+                (Self::merge_lines(lines.clone(), self.retained_crop.width() as f32, &self.state.settings), lines)
+            }
+            Err(err) => (err, Vec::new()),
+        }
+    }
+
+    // Sort top-to-bottom, with a fudge for simple cases where a line is split into multiple Lines
+    // and we want to do them left-to-right
+    fn sort_lines(lines: &mut [Line], settings: &Settings) {
+        if settings.row_clustering {
+            Self::sort_lines_by_row(lines, settings);
+            return;
+        }
+        let key = |line: &Line| {
+            if !settings.line_sort_fudge {
+                return line.mid.y;
+            }
+            let x = if settings.right_to_left { 1.0 - line.bbox.right() } else { line.left };
+            line.mid.y + x / settings.line_sort_divisor
+        };
+        lines.sort_by(|a, b| cmp_f32(&key(a), &key(b)));
+    }
+
+    // Top-to-bottom rows, each left-to-right (or right-to-left). A line is in the same row as the
+    // previous one if their centres are close enough relative to the typical line height, which
+    // copes with rows that slope a little better than the fudge does
+    fn sort_lines_by_row(lines: &mut [Line], settings: &Settings) {
+        if lines.is_empty() {
+            return;
+        }
+        let mut heights: Vec<f32> = lines.iter().map(|line| line.bbox.height()).collect();
+        heights.sort_by(cmp_f32);
+        let threshold = heights[heights.len() / 2] * settings.row_threshold;
+
+        lines.sort_by(|a, b| cmp_f32(&a.mid.y, &b.mid.y));
+        let mut rows = Vec::new();
+        let mut row_start = 0;
+        for i in 1..=lines.len() {
+            if i == lines.len() || lines[i].mid.y - lines[i - 1].mid.y > threshold {
+                rows.push(row_start..i);
+                row_start = i;
+            }
+        }
+        for row in rows {
+            if settings.right_to_left {
+                lines[row].sort_by(|a, b| cmp_f32(&b.bbox.right(), &a.bbox.right()));
+            } else {
+                lines[row].sort_by(|a, b| cmp_f32(&a.left, &b.left));
+            }
+        }
+    }
+
+    // Run OCR over the image with the chosen backend, and return the lines in the order it found them
+    async fn detect_lines(image_bytes: Vec<u8>, ocr: &OcrSettings) -> Result<Vec<Line>, String> {
+        match ocr.backend {
+            OcrBackend::Textract => Self::detect_lines_textract(image_bytes, &ocr.textract).await,
+        }
+    }
+
+    async fn detect_lines_textract(image_bytes: Vec<u8>, settings: &TextractSettings) -> Result<Vec<Line>, String> {
+        let mut loader = aws_config::defaults(aws_config::BehaviorVersion::v2024_03_28());
+        if !settings.region.trim().is_empty() {
+            loader = loader.region(aws_config::Region::new(settings.region.trim().to_owned()));
+        }
+        if !settings.profile.trim().is_empty() {
+            loader = loader.profile_name(settings.profile.trim());
+        }
+        let config = loader.load().await;
+        let client = aws_sdk_textract::Client::new(&config);
+
+        let res = client
+            .detect_document_text()
+            .document(aws_sdk_textract::types::Document::builder().bytes(aws_sdk_textract::primitives::Blob::new(image_bytes)).build())
+            .send()
+            .await;
+
+        match res {
+            Ok(doc) => {
+                let mut lines: Vec<Line> = Vec::new();
+
+                for block in doc.blocks() {
+                    if *block.block_type().unwrap() == aws_sdk_textract::types::BlockType::Line {
+                        let points: Vec<_> = block.geometry().unwrap().polygon()
+                            .iter()
+                            .map(|pt| {
+                                Vec2::new(pt.x(), pt.y())
+                            })
+                            .collect();
+
+                        let bbox = block.geometry().unwrap().bounding_box().unwrap();
+
+                        let mid = Vec2::new(bbox.left() + bbox.width() / 2.0, bbox.top() + bbox.height() / 2.0);
+                        let left = bbox.left();
+
+                        lines.push(Line {
+                            text: block.text().unwrap().to_string(),
+                            bbox: Rect::from_min_size(Pos2::new(bbox.left(), bbox.top()), Vec2::new(bbox.width(), bbox.height())),
+                            points,
+                            left,
+                            mid,
+                            confidence: block.confidence().unwrap_or(0.0),
+                            paragraph: None,
+                        });
+                    }
+                }
+
+                Ok(lines)
+            },
+            Err(err) => {
+                log::error!("OCR failed: {:?}", err);
+                Err(format!("Error: {:?}", err))
+            }
+        }
+    }
+
+    // The articles a batch extraction would fill in, with the index of their image: those with
+    // polys but no text yet
+    fn batch_todo(&self) -> Vec<(usize, Vec<Article>)> {
+        self.state.images.iter().enumerate().filter_map(|(index, filename)| {
+            let page = self.state.pages.get(filename)?;
+            let todo: Vec<Article> = page.articles.iter()
+                .filter(|article| article.kind == ArticleKind::Text && article.text.trim().is_empty() && !article.polys.is_empty())
+                .cloned()
+                .collect();
+            (!todo.is_empty()).then_some((index, todo))
+        }).collect()
+    }
+
+    fn statistics(&self) -> Statistics {
+        let mut stats = Statistics {
+            pages: self.state.images.len(),
+            annotated: 0,
+            blank: 0,
+            articles: 0,
+            verified: 0,
+            words: 0,
+            articles_per_page: BTreeMap::new(),
+            dates: None,
+            undated: 0,
+            ocr_calls: 0,
+        };
+        let mut dates = Vec::new();
+        for filename in &self.state.images {
+            let page = self.state.pages.get(filename);
+            if page.is_some_and(|page| page.blank) {
+                stats.blank += 1;
+                continue;
+            }
+            let articles = page.map_or(&[][..], |page| &page.articles);
+            if !articles.is_empty() {
+                stats.annotated += 1;
+            }
+            *stats.articles_per_page.entry(articles.len()).or_default() += 1;
+            for article in articles {
+                stats.articles += 1;
+                stats.verified += article.verified as usize;
+                stats.words += article.text.split_whitespace().count();
+                stats.ocr_calls += (0..article.polys.len()).filter(|&i| {
+                    article.raw_ocr.get(i).is_some_and(Option::is_some) || article.provenance.get(i).is_some_and(Option::is_some)
+                }).count();
+            }
+            if let Some(page) = page {
+                stats.ocr_calls += page.scratchpad.iter().filter(|scrap| scrap.raw_ocr.is_some() || scrap.provenance.is_some()).count();
+            }
+            // Dates are free text, but ones starting with a year sort into order as strings
+            // when they're written year first
+            match page.and_then(|page| page.date.as_deref()).map(str::trim) {
+                Some(date) if date.len() >= 4 && date.as_bytes()[..4].iter().all(u8::is_ascii_digit) => dates.push(date),
+                _ => stats.undated += 1,
+            }
+        }
+        dates.sort();
+        if let (Some(first), Some(last)) = (dates.first(), dates.last()) {
+            stats.dates = Some((first.to_string(), last.to_string()));
+        }
+        stats
+    }
+
+    // Walk the same regions as run_batch, reading only the images' sizes, to say how many OCR calls
+    // it would make and which regions it would skip
+    fn estimate_batch(&self) -> BatchEstimate {
+        let mut estimate = BatchEstimate { pages: 0, articles: 0, calls: 0, skipped: Vec::new() };
+        for (index, todo) in self.batch_todo() {
+            let filename = &self.state.images[index];
+            let bounds = match image::image_dimensions(format!("{}{}", self.project.images, filename)) {
+                Ok((width, height)) => Rect::from_min_max(Pos2::ZERO, Pos2::new(width as f32, height as f32)),
+                Err(err) => {
+                    let regions: usize = todo.iter().map(|article| article.polys.len()).sum();
+                    estimate.skipped.push(format!("{}: {} regions, because the image can't be read: {}", filename, regions, err));
+                    continue;
+                }
+            };
+            // As run_batch counts them, only for pages whose image can be loaded
+            estimate.pages += 1;
+            estimate.articles += todo.len();
+            for article in &todo {
+                for (i, vertexes) in article.polys.iter().enumerate() {
+                    // Filling by winding, the signed area can't tell, and only extracting would
+                    let area = Self::poly_area(vertexes);
+                    if !self.state.settings.nonzero_fill && area < MIN_CROP_AREA {
+                        estimate.skipped.push(format!("{}: article {}: poly {} has an empty crop (area {:.1})", filename, article.id, i, area));
+                    } else if !vertexes.iter().fold(Rect::NOTHING, |rect, &p| rect.union(Rect::from_min_max(p, p))).intersects(bounds) {
+                        estimate.skipped.push(format!("{}: article {}: poly {} is outside the image", filename, article.id, i));
+                    } else {
+                        estimate.calls += 1;
+                    }
+                }
+            }
+        }
+        estimate
+    }
+
+    // OCR every poly of every article that doesn't have any text yet, without the GUI
+    fn run_batch(&mut self) {
+        if let Some(problem) = &self.images_problem {
+            eprintln!("{}", problem);
+            return;
+        }
+        let open_image = self.state.open_image;
+        let mut problems = Vec::new();
+        let (mut pages, mut articles) = (0, 0);
+        for (index, todo) in self.batch_todo() {
+            let filename = self.state.images[index].clone();
+            println!("{}: {} articles", filename, todo.len());
+            self.state.open_image = index;
+            // Once for the page, rather than every poly's crop coming out empty
+            let image = Self::decode_image(&self.open_image_path());
+            if let Err(err) = &image {
+                problems.push(format!("{}: couldn't load the image, so its {} articles were skipped: {}", filename, todo.len(), err));
+                continue;
+            }
+            pages += 1;
+            articles += todo.len();
+            self.loading = None;
+            self.reset_page();
+            self.image_loaded(image);
+
+            for todo_article in todo {
+                let id = todo_article.id;
+                let mut text = String::new();
+                let mut raw_ocr = Vec::new();
+                let mut provenance = Vec::new();
+                let mut confident = true;
+                for (i, vertexes) in todo_article.polys.iter().enumerate() {
+                    self.vertexes = vertexes.clone();
+                    self.holes = todo_article.poly_holes(i).to_vec();
+                    let Some(image) = self.extract_image() else {
+                        problems.push(format!("{}: article {}: poly {} has an empty crop (area {:.1})", filename, id, i, Self::poly_area(vertexes)));
+                        raw_ocr.push(None);
+                        provenance.push(None);
+                        confident = false;
+                        continue;
+                    };
+                    match self.runtime.block_on(Self::detect_lines(image, &self.projects.ocr)) {
+                        Ok(mut lines) => {
+                            Self::sort_lines(&mut lines, &self.state.settings);
+                            let crop_size = Vec2::new(self.crop_image.width() as f32, self.crop_image.height() as f32);
+                            let lines = Self::join_split_lines(lines, crop_size, &self.state.settings);
+                            let width = self.crop_image.width() as f32;
+                            if lines.is_empty() {
+                                problems.push(format!("{}: article {}: poly {}: no text detected", filename, id, i));
+                            }
+                            confident &= self.confident(&lines);
+                            text.push_str(Self::merge_lines(lines.clone(), width, &self.state.settings).trim_end());
+                            text.push('\n');
+                            raw_ocr.push(Some(RawOcr { width, crop: Some(self.crop_rect), paragraph: false, separator: None, lines }));
+                            provenance.push(Some(self.current_provenance(&format!("{} (batch)", self.projects.ocr.backend.name()))));
+                        }
+                        Err(err) => {
+                            problems.push(format!("{}: article {}: poly {}: {}", filename, id, i, err));
+                            raw_ocr.push(None);
+                            provenance.push(None);
+                            confident = false;
+                        }
+                    }
+                }
+                let store_raw_ocr = self.state.settings.store_raw_ocr;
+                let auto_verify = self.state.settings.auto_verify;
+                let article = self.state.page().article_mut(id).unwrap();
+                article.text = text;
+                article.provenance = provenance;
+                article.touch();
+                if auto_verify {
+                    article.verified = confident;
+                }
+                if store_raw_ocr {
+                    article.raw_ocr = raw_ocr;
+                }
+            }
+        }
+        self.vertexes.clear();
+        self.holes.clear();
+        self.state.open_image = open_image;
+        self.save();
+
+        println!("Extracted {} articles on {} pages", articles, pages);
+        if !problems.is_empty() {
+            eprintln!("Skipped {} problem regions:", problems.len());
+            for problem in problems {
+                eprintln!("  {}", problem);
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+struct Scaler {
+    scale: f32, // screen-space units per image-space pixel
+    viewport: Vec2, // size in screen-space
+    offset: Vec2, // screen-space coords
+    image_rect: Rect, // screen-space coords of viewport
+}
+
+impl Scaler {
+    fn screen_to_image(&self, screen: Pos2) -> Pos2 {
+        ((screen.to_vec2() - self.image_rect.left_top().to_vec2() + self.offset) / self.scale).to_pos2()
+    }
+
+    fn image_to_screen(&self, image: Pos2) -> Pos2 {
+        ((image.to_vec2() * self.scale) - self.offset + self.image_rect.left_top().to_vec2()).to_pos2()
+    }
+}
+
+impl eframe::App for MyApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Closing the window goes through the same check for unsaved changes as switching projects
+        if ctx.input(|i| i.viewport().close_requested()) && !self.quitting {
+            match &mut self.leaving {
+                // Already asking about switching projects, so ask about quitting instead
+                Some((leave, _)) => *leave = Leave::Quit,
+                None => self.leave(ctx, Leave::Quit),
+            }
+            if self.leaving.is_some() {
+                ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            }
+        }
+
+        self.poll_external_edit(ctx);
+        self.poll_blank_detection(ctx);
+
+        if self.state.images.is_empty() || self.images_problem.is_some() {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                match &self.images_problem {
+                    Some(problem) => ui.colored_label(Color32::YELLOW, problem),
+                    None => ui.label(format!("No images in {}", self.project.annotations)),
+                };
+                ui.label("The images folder should contain the .jpg, .png or .tif scans for this project");
+                ui.horizontal(|ui| {
+                    ui.label("Images folder");
+                    ui.text_edit_singleline(&mut self.images_path_edit);
+                    if ui.button("Use this folder").clicked() {
+                        self.set_images_path(self.images_path_edit.clone());
+                    }
+                });
+                if self.images_problem.is_none() && ui.add_enabled(!self.readonly, egui::Button::new(format!("Scan {}", self.project.images))).clicked() {
+                    match self.state.scan_images(&self.project.images) {
+                        Ok(_) => self.load_image(),
+                        Err(err) => self.status = format!("Failed to scan {}: {}", self.project.images, err),
+                    }
+                }
+                ui.label(&self.status);
+                self.projects_ui(ui);
+            });
+            self.leave_window(ctx);
+            return;
+        }
+
+        if let Some(loading) = &self.loading {
+            match loading.try_recv() {
+                Ok(image) => {
+                    self.loading = None;
+                    if self.state.settings.cross_fade {
+                        let previous = std::mem::replace(&mut self.retained_image, RetainedImage::from_color_image("black", ColorImage::new([1, 1], Color32::BLACK)));
+                        self.fade_from = Some((previous, ctx.input(|i| i.time)));
+                    }
+                    self.image_loaded(image);
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => ctx.request_repaint_after(std::time::Duration::from_millis(50)),
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => self.loading = None,
+            }
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            // Whatever space there is at the current UI scale
+            let viewport = ui.available_size();
+
+            // B toggles the boxes, and holding Alt temporarily does the opposite
+            if !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::B)) {
+                self.show_boxes = !self.show_boxes;
+            }
+            let show_boxes = self.show_boxes != ui.input(|i| i.modifiers.alt);
+
+            // N appends the draft, to a new article if none is open
+            if !self.readonly && !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::N)) {
+                self.append_to_new_article();
+            }
+
+            // E extracts the poly again without redrawing it
+            if !self.readonly && !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::E)) {
+                self.extract();
+            }
+
+            // P extracts again with the next preprocessing preset, and Shift+P the previous one
+            if !self.readonly && !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::P)) {
+                let forwards = !ctx.input(|i| i.modifiers.shift);
+                self.cycle_preset(forwards);
+            }
+
+            // Ctrl+C copies the current poly, and Ctrl+V pastes one from the system clipboard if it
+            // has one, or else the last one copied here
+            if !ctx.wants_keyboard_input() {
+                let (copy, paste) = ctx.input(|i| {
+                    let copy = i.events.iter().any(|event| matches!(event, egui::Event::Copy));
+                    let paste = i.events.iter().find_map(|event| match event {
+                        egui::Event::Paste(text) => Some(text.clone()),
+                        _ => None,
+                    });
+                    (copy, paste)
+                });
+                if copy && self.vertexes.len() >= 3 {
+                    self.copy_poly(ctx, CopiedPoly { vertexes: self.vertexes.clone(), holes: self.holes.clone() });
+                }
+                if let Some(text) = paste.filter(|_| !self.readonly) {
+                    match serde_json::from_str::<CopiedPoly>(&text).ok().or_else(|| self.copied_poly.clone()) {
+                        Some(poly) => self.paste_poly(poly),
+                        None => self.status = "The clipboard doesn't have a poly in it".to_owned(),
+                    }
+                }
+            }
+
+            // Up and Down choose a poly of the open article, and Delete removes it
+            if !ctx.wants_keyboard_input() {
+                let (up, down, delete) = ctx.input(|i| (i.key_pressed(egui::Key::ArrowUp), i.key_pressed(egui::Key::ArrowDown), i.key_pressed(egui::Key::Delete)));
+                if up || down {
+                    self.step_poly(down);
+                }
+                if delete && !self.readonly {
+                    self.delete_selected_poly();
+                }
+            }
+
+            // Ctrl+Z undoes the last edit to this page's articles, when a text box isn't handling it
+            if !self.readonly && !ctx.wants_keyboard_input() && ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Z)) {
+                let filename = self.state.images[self.state.open_image].clone();
+                self.undo(&filename);
+            }
+
+            let response = ui.allocate_response(viewport, Sense::click_and_drag());
+            let image_rect = response.rect;
+
+            // Scroll to zoom around the pointer. Everything we store is in image space,
+            // so in-progress vertexes don't move relative to the image
+            if let Some(pointer) = response.hover_pos() {
+                let scroll = ui.input(|i| i.raw_scroll_delta.y);
+                if scroll != 0.0 {
+                    let before = Scaler { scale: self.scale, viewport, offset: self.offset, image_rect }.screen_to_image(pointer);
+                    self.scale = (self.scale * (scroll / 200.0).exp()).clamp(MIN_SCALE, MAX_SCALE);
+                    self.offset = before.to_vec2() * self.scale + image_rect.min.to_vec2() - pointer.to_vec2();
+                }
+            }
+            let scale = self.scale;
+
+            let scaler = Scaler {
+                scale,
+                viewport,
+                offset: self.offset,
+                image_rect,
+            };
+
+            let image_mesh = |retained: &RetainedImage, tint: Color32| {
+                let mut mesh = egui::Mesh::with_texture(retained.texture_id(ctx));
+                mesh.add_rect_with_uv(
+                    image_rect,
+                    Rect::from_min_max(
+                        (self.offset / (retained.size_vec2() * scale)).to_pos2(),
+                        ((self.offset + viewport) / (retained.size_vec2() * scale)).to_pos2(),
+                    ),
+                    tint,
+                );
+                Shape::mesh(mesh)
+            };
+            ui.painter().add(image_mesh(&self.retained_image, Color32::WHITE));
+
+            // Fade out the previous image over the new one
+            let mut faded = false;
+            if let Some((previous, start)) = &self.fade_from {
+                let t = ((ctx.input(|i| i.time) - start) / CROSS_FADE_SECONDS) as f32;
+                if t < 1.0 {
+                    ui.painter().add(image_mesh(previous, Color32::WHITE.gamma_multiply(1.0 - t)));
+                    ctx.request_repaint();
+                } else {
+                    faded = true;
+                }
+            }
+            if faded {
+                self.fade_from = None;
+            }
+
+            // Points outside the image get clamped when extracting, so the crop would silently be wrong
+            let bounds = self.image_bounds();
+            let outside = self.state.page().articles.iter().flat_map(|article| &article.polys)
+                .chain(std::iter::once(&self.vertexes))
+                .filter(|vertexes| vertexes.iter().any(|&p| !bounds.contains(p)))
+                .count();
+
+            if show_boxes {
+                // Draw the open article last, so it's on top of any others that overlap it
+                let open_article = self.open_article;
+                let confidence_tint = self.state.settings.confidence_tint;
+                let articles = &self.state.page().articles;
+                let ordered = articles.iter().filter(|article| Some(article.id) != open_article)
+                    .chain(articles.iter().filter(|article| Some(article.id) == open_article));
+                for article in ordered {
+                    let fill = if Some(article.id) == open_article {
+                        Color32::from_rgba_unmultiplied(0, 0, 96, 60)
+                    } else {
+                        Color32::from_rgba_unmultiplied(0, 0, 0, 50)
+                    };
+                    for (i, vertexes) in article.polys.iter().enumerate() {
+                        for hole in article.poly_holes(i) {
+                            let points = hole.iter().map(|&p| scaler.image_to_screen(p)).collect();
+                            ui.painter().add(PathShape::closed_line(points, Stroke::new(1.0, Color32::from_rgb(255, 128, 0))));
+                        }
+
+                        let points: Vec<Pos2> = vertexes.iter().map(|&p| scaler.image_to_screen(p)).collect();
+
+                        // egui's PathShape fill assumes convex, which is not true, so triangulate it ourselves
+                        let mut mesh = egui::Mesh::default();
+                        for &p in &points {
+                            mesh.colored_vertex(p, fill);
+                        }
+                        for [a, b, c] in Self::triangulate(&points) {
+                            mesh.add_triangle(a as u32, b as u32, c as u32);
+                        }
+                        ui.painter().add(Shape::mesh(mesh));
+
+                        // The mesh isn't anti-aliased, but the outline is
+                        let confidence = article.raw_ocr.get(i).and_then(|ocr| ocr.as_ref()).and_then(RawOcr::mean_confidence);
+                        match confidence {
+                            Some(confidence) if confidence_tint => {
+                                ui.painter().add(PathShape::closed_line(points, Stroke::new(2.0, Self::confidence_color(confidence))));
+                            }
+                            _ => {
+                                ui.painter().add(PathShape::closed_line(points, Stroke::new(1.0, fill)));
+                            }
+                        }
+
+                        for &p in vertexes.iter().filter(|&&p| !bounds.contains(p)) {
+                            ui.painter().circle_stroke(scaler.image_to_screen(p), 4.0, Stroke::new(2.0, Color32::YELLOW));
+                        }
+                    }
+                }
+
+                // Reading-order badges
+                for (i, article) in self.state.page().articles.iter().enumerate() {
+                    if let Some(centroid) = Self::article_centroid(&article.polys) {
+                        let center = scaler.image_to_screen(centroid);
+                        ui.painter().circle_filled(center, 8.0, Color32::from_rgba_unmultiplied(0, 0, 128, 200));
+                        ui.painter().text(center, egui::Align2::CENTER_CENTER, i.to_string(), FontId::proportional(9.0), Color32::WHITE);
+                    }
+                }
+
+                // Say which article is under the pointer
+                if let Some(p) = response.hover_pos() {
+                    let p = scaler.screen_to_image(p);
+                    let articles = &self.state.page().articles;
+                    if let Some((i, article)) = articles.iter().enumerate().find(|(_, article)| Self::point_in_polys(p, &article.polys)) {
+                        let first_line = article.text.lines().find(|line| !line.trim().is_empty()).unwrap_or("");
+                        response.clone().on_hover_text_at_pointer(format!("({}) {}", i, first_line));
+                    }
+                }
+            }
+
+            // The editors fill this in again while they have a selection
+            for &rect in &std::mem::take(&mut self.selection_highlight) {
+                let rect = Rect::from_min_max(scaler.image_to_screen(rect.min), scaler.image_to_screen(rect.max));
+                ui.painter().rect(rect, 0.0, Color32::from_rgba_unmultiplied(255, 255, 0, 60), Stroke::new(1.0, Color32::YELLOW));
+            }
+
+            if response.dragged_by(egui::PointerButton::Secondary) {
+                self.offset -= response.drag_delta();
+            }
+
+            // In fast entry mode, dragging draws a box and extracts it straight away
+            if self.fast_entry && !self.readonly && !self.measuring && !self.choosing_append_target {
+                if response.drag_started_by(egui::PointerButton::Primary) {
+                    self.box_start = ctx.input(|i| i.pointer.press_origin()).map(|p| scaler.screen_to_image(p));
+                }
+                if let (Some(start), Some(p)) = (self.box_start, response.interact_pointer_pos()) {
+                    let end = scaler.screen_to_image(p);
+                    self.vertexes = vec![start, Pos2::new(end.x, start.y), end, Pos2::new(start.x, end.y)];
+                    self.holes.clear();
+                }
+                if response.drag_stopped_by(egui::PointerButton::Primary) && self.box_start.take().is_some() {
+                    self.extract();
+                }
+            }
+
+            if !self.readonly && !self.vertexes.is_empty() && response.clicked_by(egui::PointerButton::Middle) {
+                self.vertexes.pop();
+            }
+
+            // D toggles measuring, where clicks pick two points to measure between instead of drawing polys
+            if !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::D)) {
+                self.measuring = !self.measuring;
+                self.measure.clear();
+            }
+            if self.choosing_append_target {
+                if ctx.input(|i| i.key_pressed(egui::Key::Escape)) || self.draft_appended || self.draft_text.is_empty() {
+                    self.choosing_append_target = false;
+                }
+                ui.painter().text(
+                    image_rect.center_top() + Vec2::new(0.0, 32.0),
+                    egui::Align2::CENTER_TOP,
+                    "Click an article's poly or header to append the draft to it (Esc to cancel)",
+                    FontId::new(16.0, FontFamily::Proportional),
+                    Color32::from_rgb(0, 192, 255),
+                );
+                if response.clicked_by(egui::PointerButton::Primary) {
+                    let p = scaler.screen_to_image(response.interact_pointer_pos().unwrap());
+                    match self.state.page().articles.iter().find(|article| Self::point_in_polys(p, &article.polys)) {
+                        Some(article) => {
+                            let id = article.id;
+                            self.append_to_article(id);
+                        }
+                        None => self.status = "There's no article there".to_owned(),
+                    }
+                }
+            } else if self.measuring {
+                if response.clicked_by(egui::PointerButton::Primary) {
+                    if self.measure.len() == 2 {
+                        self.measure.clear();
+                    }
+                    self.measure.push(scaler.screen_to_image(response.interact_pointer_pos().unwrap()));
+                }
+                let end = match (self.measure.as_slice(), response.hover_pos()) {
+                    ([a, b], _) => Some((*a, *b)),
+                    ([a], Some(hover)) => Some((*a, scaler.screen_to_image(hover))),
+                    _ => None,
+                };
+                if let Some((a, b)) = end {
+                    let (sa, sb) = (scaler.image_to_screen(a), scaler.image_to_screen(b));
+                    ui.painter().line_segment([sa, sb], Stroke::new(2.0, Color32::from_rgb(0, 192, 255)));
+                    let d = b - a;
+                    // Angle from horizontal, with positive being anticlockwise like in maths even though y is down
+                    let angle = (-d.y).atan2(d.x).to_degrees();
+                    ui.painter().text(
+                        sb + Vec2::new(8.0, -8.0),
+                        egui::Align2::LEFT_BOTTOM,
+                        format!("{:.1}px{}, {:.2}°", d.length(), Self::physical_length(d.length(), self.state.settings.dpi), angle),
+                        FontId::new(14.0, FontFamily::Proportional),
+                        Color32::from_rgb(0, 192, 255),
+                    );
+                }
+            } else if !self.readonly && response.clicked_by(egui::PointerButton::Primary) {
+                let modifiers = ctx.input(|i| i.modifiers);
+                // Starting a new poly would lose a carefully placed one, so optionally require Ctrl for that
+                let guarded = self.state.settings.guard_clear && self.vertexes.len() > 3 && !modifiers.command;
+                if modifiers.shift {
+                    self.vertexes.push(scaler.screen_to_image(response.interact_pointer_pos().unwrap()));
+                    self.drawing = true;
+                } else if guarded {
+                    self.status = format!("Ctrl+click to discard the {}-point poly and start a new one", self.vertexes.len());
+                } else {
+                    self.previous_vertexes = Some((std::mem::take(&mut self.vertexes), std::mem::take(&mut self.holes)));
+                    self.vertexes.push(scaler.screen_to_image(response.interact_pointer_pos().unwrap()));
+                    self.drawing = true;
+                }
+
+                // The second click of a Shift+double-click lands on the first, so it just finishes the poly
+                if response.double_clicked_by(egui::PointerButton::Primary) && modifiers.shift {
+                    self.vertexes.pop();
+                    self.finish_poly();
+                }
+            }
+
+            // Enter finishes the poly too
+            if self.drawing && !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
+                self.finish_poly();
+            }
+
+            let adding_vertex = !self.readonly && !self.vertexes.is_empty() && ctx.input(|i| i.modifiers.shift);
+            let mut temp_vertex = false;
+            if adding_vertex {
+                if let Some(p) = response.hover_pos() {
+                    self.vertexes.push(scaler.screen_to_image(p));
+                    temp_vertex = true;
+                }
+            }
+
+            if show_boxes {
+                // Screen-space radius stays the same size whatever the zoom, while image-space radius
+                // stays the same size relative to the image (within limits, so it doesn't vanish)
+                let radius = if self.state.settings.vertex_radius_image_space {
+                    (self.state.settings.vertex_radius * scale).clamp(MIN_VERTEX_RADIUS, MAX_VERTEX_RADIUS)
+                } else {
+                    self.state.settings.vertex_radius
+                };
+                for &vertex in &self.vertexes {
+                    let color = if bounds.contains(vertex) { Color32::from_rgba_unmultiplied(255, 0, 0, 255) } else { Color32::YELLOW };
+                    ui.painter().add(Shape::Circle(
+                        CircleShape {
+                            center: scaler.image_to_screen(vertex),
+                            radius,
+                            fill: Color32::TRANSPARENT,
+                            stroke: Stroke::new(1.0, color)
+                        }
+                    ));
+                }
+                // While drawing, the edge back to the start is only provisional, so it's fainter
+                let points: Vec<Pos2> = self.vertexes.iter().map(|&p| scaler.image_to_screen(p)).collect();
+                if self.drawing && self.state.settings.preview_closing_edge && points.len() >= 3 {
+                    ui.painter().line_segment([points[points.len() - 1], points[0]], Stroke::new(1.0, Color32::from_rgba_unmultiplied(255, 0, 0, 96)));
+                }
+                ui.painter().add(Shape::Path(
+                    PathShape {
+                        points,
+                        closed: !self.drawing,
+                        fill: Color32::TRANSPARENT,
+                        stroke: PathStroke::new(2.0, Color32::from_rgba_unmultiplied(255, 0, 0, 255))
+                    }
+                ));
+                for hole in &self.holes {
+                    let points = hole.iter().map(|&p| scaler.image_to_screen(p)).collect();
+                    ui.painter().add(PathShape::closed_line(points, Stroke::new(2.0, Color32::from_rgb(255, 128, 0))));
+                }
+            }
+
+            if temp_vertex {
+                self.vertexes.pop();
+            }
+
+            if outside > 0 && self.loading.is_none() {
+                ui.painter().text(
+                    image_rect.center_top() + Vec2::new(0.0, 8.0),
+                    egui::Align2::CENTER_TOP,
+                    format!("{} poly(s) extend outside the {}x{} image", outside, self.image.width(), self.image.height()),
+                    FontId::new(16.0, FontFamily::Proportional),
+                    Color32::YELLOW,
+                );
+            }
+
+            if self.loading.is_some() {
+                ui.painter().rect_filled(image_rect, 0.0, Color32::from_black_alpha(128));
+                ui.painter().text(
+                    image_rect.center(),
+                    egui::Align2::CENTER_CENTER,
+                    format!("Loading {}...", self.state.images[self.state.open_image]),
+                    FontId::new(24.0, FontFamily::Proportional),
+                    Color32::WHITE,
+                );
+            }
+
+            // Down the right of the same area as the image, which is inset by the panel's margin
+            let sidebar_rect = Rect::from_min_max(Pos2::new(image_rect.right() - SIDEBAR_WIDTH, image_rect.top()), image_rect.max);
+
+            // A configurable key hides the sidebar and popup, to see the whole image
+            let hide_key = egui::Key::from_name(&self.state.settings.hide_chrome_key);
+            if !ctx.wants_keyboard_input() && hide_key.is_some_and(|key| ctx.input(|i| i.key_pressed(key))) {
+                self.hide_chrome = !self.hide_chrome;
+            }
+            let chrome = !self.hide_chrome;
+
+            let visible = if chrome {
+                Rect::from_min_max(image_rect.min, Pos2::new(sidebar_rect.left(), image_rect.bottom()))
+            } else {
+                image_rect
+            };
+
+            // M toggles the minimap
+            if !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::M)) {
+                self.show_minimap = !self.show_minimap;
+            }
+            if self.show_minimap && chrome {
+                self.minimap(ui, visible);
+            }
+            if self.show_ruler {
+                self.ruler(ui, visible);
+            }
+
+            // Page Up/Down step across the image a screenful at a time, in reading order,
+            // which is easier than panning across a wide spread
+            if !ctx.wants_keyboard_input() {
+                let (forwards, backwards) = ctx.input(|i| (i.key_pressed(egui::Key::PageDown), i.key_pressed(egui::Key::PageUp)));
+                if forwards || backwards {
+                    self.step_region(visible.size(), forwards);
+                }
+            }
+
+            // Ctrl+Enter saves and moves on in fast entry mode, even from the editor
+            if self.fast_entry && !self.readonly && ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Enter)) {
+                self.save_and_advance();
+            }
+            if chrome && !self.readonly && self.fast_entry {
+                let rect = Rect::from_min_max(Pos2::new(visible.left(), visible.bottom() - FAST_ENTRY_HEIGHT), visible.right_bottom());
+                ui.allocate_ui_at_rect(rect, |ui| {
+                    self.fast_entry_panel(ui);
+                });
+            }
+
+            // Don't extract from the previous image while the new one is loading
+            if chrome && !self.readonly && !self.fast_entry && self.vertexes.len() >= 4 && self.loading.is_none() {
+                let x0 = self.vertexes.iter().map(|p| p.x).min_by(cmp_f32).unwrap();
+                let x1 = self.vertexes.iter().map(|p| p.x).max_by(cmp_f32).unwrap();
+                let y0 = self.vertexes.iter().map(|p| p.y).min_by(cmp_f32).unwrap();
+
+                // Keep the popup within the part of the viewport that's not covered by the sidebar,
+                // flipping it to the left of the selection if there's no room on the right
+                let size = Vec2::new(500.0, 200.0);
+                let area = Rect::from_min_max(image_rect.min, Pos2::new(sidebar_rect.left(), image_rect.bottom()));
+                let mut pos = scaler.image_to_screen(Pos2::new(x1 + 20.0, y0 - 20.0));
+                if pos.x + size.x > area.right() {
+                    pos.x = scaler.image_to_screen(Pos2::new(x0 - 20.0, y0)).x - size.x;
+                }
+                pos.x = pos.x.clamp(area.left(), (area.right() - size.x).max(area.left()));
+                pos.y = pos.y.clamp(area.top(), (area.bottom() - size.y).max(area.top()));
+
+                ui.allocate_ui_at_rect(
+                    Rect::from_min_size(pos, size),
+                    |ui| {
+                        self.popup(ui);
+                    },
+                );
+            }
+
+            if chrome {
+                ui.allocate_ui_at_rect(
+                    sidebar_rect,
+                    |ui| {
+                        egui::Frame::none()
+                            .fill(egui::Color32::from_gray(192))
+                            .show(ui, |ui| {
+                                // The fill covers the whole height, however much is open inside it
+                                ui.set_min_size(sidebar_rect.size());
+                                self.sidebar(scaler, ui);
+                            });
+                    },
+                );
+            }
+        });
+
+        self.duplicates_window(ctx);
+        self.unsaved_draft_window(ctx);
+        self.out_of_bounds_window(ctx);
+        self.text_changes_window(ctx);
+        self.gremlins_window(ctx);
+        self.batch_summary_window(ctx);
+        self.history_window(ctx);
+        self.leave_window(ctx);
+    }
+}
+
+impl MyApp {
+    fn duplicates_window(&mut self, ctx: &egui::Context) {
+        let Some(duplicates) = &self.duplicates else {
+            return;
+        };
+
+        let mut open = true;
+        let mut merge = None;
+        let mut delete = None;
+        let page = &self.state.pages[&self.state.images[self.state.open_image]];
+        // Pairs where either article has since been deleted are dropped
+        let pairs: Vec<_> = duplicates
+            .iter()
+            .filter_map(|dup| Some((dup, page.article_index(dup.a)?, page.article_index(dup.b)?)))
+            .collect();
+        egui::Window::new("Duplicates").open(&mut open).show(ctx, |ui| {
+            if pairs.is_empty() {
+                ui.label("No duplicates found");
+            }
+            for &(dup, a, b) in &pairs {
+                ui.separator();
+                ui.label(format!(
+                    "({}) vs ({}): text {:.0}%, overlap {:.0}%",
+                    a, b, dup.text_similarity * 100.0, dup.overlap * 100.0
+                ));
+                for i in [a, b] {
+                    ui.label(format!("({}) {}...", i, page.articles[i].text.replace("\n", " ").chars().take(60).collect::<String>()));
+                }
+                ui.add_enabled_ui(!self.readonly, |ui| ui.horizontal(|ui| {
+                    if ui.button("Merge").clicked() {
+                        merge = Some((dup.a, dup.b));
+                    }
+                    if ui.button(format!("Delete ({})", a)).clicked() {
+                        delete = Some(dup.a);
+                    }
+                    if ui.button(format!("Delete ({})", b)).clicked() {
+                        delete = Some(dup.b);
+                    }
+                }));
+            }
+        });
+
+        if merge.is_some() || delete.is_some() {
+            self.checkpoint();
+        }
+        let page = self.state.page();
+        if let Some((a, b)) = merge.filter(|&(a, _)| page.article_index(a).is_some()) {
+            if let Some(other) = page.article_index(b).map(|b| page.articles.remove(b)) {
+                let article = page.article_mut(a).unwrap();
+                if !other.text.is_empty() {
+                    article.text.push('\n');
+                    article.text.push_str(&other.text);
+                }
+                article.append_polys(other);
+            }
+        }
+        if let Some(i) = delete.and_then(|id| page.article_index(id)) {
+            page.articles.remove(i);
+        }
+
+        if !open {
+            self.duplicates = None;
+        } else if merge.is_some() || delete.is_some() {
+            self.open_article = None;
+            self.duplicates = Some(self.find_duplicates());
+        }
+    }
+
+    fn out_of_bounds_window(&mut self, ctx: &egui::Context) {
+        if self.out_of_bounds.is_empty() {
+            return;
+        }
+
+        enum Choice { Clamp, Drop, Ignore }
+
+        let mut choice = None;
+        let page = &self.state.pages[&self.state.images[self.state.open_image]];
+        egui::Window::new("Polys outside the image").collapsible(false).show(ctx, |ui| {
+            ui.label(format!("The image is {}x{}, but these polys extend outside it:", self.image.width(), self.image.height()));
+            for &(id, poly) in &self.out_of_bounds {
+                if let Some(i) = page.article_index(id) {
+                    ui.label(format!("Article ({}), poly {}", i, poly));
+                }
+            }
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!self.readonly, egui::Button::new("Clamp")).clicked() {
+                    choice = Some(Choice::Clamp);
+                }
+                if ui.add_enabled(!self.readonly, egui::Button::new("Drop")).clicked() {
+                    choice = Some(Choice::Drop);
+                }
+                // Even read-only, the window can be dismissed
+                if ui.button("Ignore").clicked() {
+                    choice = Some(Choice::Ignore);
+                }
+            });
+        });
+
+        let Some(choice) = choice else {
+            return;
+        };
+        if !matches!(choice, Choice::Ignore) {
+            self.checkpoint();
+        }
+        let max = Pos2::new(self.image.width() as f32, self.image.height() as f32);
+        let page = self.state.page();
+        // Go backwards so dropping doesn't shift the later indexes
+        for &(id, poly) in self.out_of_bounds.iter().rev() {
+            let Some(article) = page.article_mut(id) else {
+                continue;
+            };
+            match choice {
+                Choice::Clamp => {
+                    let holes = article.holes.get_mut(poly).into_iter().flatten().flatten();
+                    for p in article.polys[poly].iter_mut().chain(holes) {
+                        *p = p.clamp(Pos2::ZERO, max);
+                    }
+                    article.touch();
+                }
+                Choice::Drop => {
+                    article.remove_poly(poly);
+                }
+                Choice::Ignore => {}
+            }
+        }
+        self.out_of_bounds.clear();
+    }
+
+    // Remember the extraction that's just been made, so it can be reopened if it's discarded
+    fn record_extraction(&mut self) {
+        let crop = &self.crop_image;
+        let egui_image = ColorImage::from_rgb([crop.width() as _, crop.height() as _], crop.as_flat_samples().as_slice());
+        self.history.push_front(Extraction {
+            page: self.state.images[self.state.open_image].clone(),
+            vertexes: self.vertexes.clone(),
+            holes: self.holes.clone(),
+            crop_image: self.crop_image.clone(),
+            crop_rect: self.crop_rect,
+            crop_mask: self.crop_mask.clone(),
+            histogram: self.histogram.clone(),
+            retained_crop: RetainedImage::from_color_image("history crop", egui_image),
+            text: self.draft_text.clone(),
+            lines: self.lines.clone(),
+            provenance: self.provenance.clone(),
+        });
+        self.history.truncate(HISTORY_LENGTH);
+    }
+
+    fn history_window(&mut self, ctx: &egui::Context) {
+        if !self.show_history {
+            return;
+        }
+
+        let open_page = &self.state.images[self.state.open_image];
+        let mut reopen = None;
+        egui::Window::new("Extraction history").open(&mut self.show_history).show(ctx, |ui| {
+            egui::ScrollArea::vertical().max_height(500.0).show(ui, |ui| {
+                for (i, extraction) in self.history.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let size = extraction.crop_rect.size();
+                        let thumbnail_scale = f32::min(120.0 / size.x, 80.0 / size.y);
+                        ui.add(egui::Image::new((extraction.retained_crop.texture_id(ui.ctx()), size * thumbnail_scale)));
+                        ui.vertical(|ui| {
+                            ui.label(extraction.text.replace('\n', " ").chars().take(80).collect::<String>());
+                            ui.horizontal(|ui| {
+                                // The poly only makes sense on the page it was drawn on
+                                if ui.add_enabled(extraction.page == *open_page, egui::Button::new("Reopen")).clicked() {
+                                    reopen = Some(i);
+                                }
+                                ui.label(&extraction.page);
+                            });
+                        });
+                    });
+                }
+            });
+        });
+
+        if let Some(i) = reopen {
+            let extraction = &self.history[i];
+            self.vertexes = extraction.vertexes.clone();
+            self.holes = extraction.holes.clone();
+            self.crop_image = extraction.crop_image.clone();
+            self.crop_rect = extraction.crop_rect;
+            self.crop_mask = extraction.crop_mask.clone();
+            self.histogram = extraction.histogram.clone();
+            let crop = &self.crop_image;
+            let egui_image = ColorImage::from_rgb([crop.width() as _, crop.height() as _], crop.as_flat_samples().as_slice());
+            self.retained_crop = RetainedImage::from_color_image("crop", egui_image);
+            let source = image::imageops::crop_imm(&self.image, self.crop_rect.min.x as u32, self.crop_rect.min.y as u32, crop.width(), crop.height()).to_image();
+            let egui_image = ColorImage::from_rgb([source.width() as _, source.height() as _], source.as_flat_samples().as_slice());
+            self.retained_source_crop = RetainedImage::from_color_image("source crop", egui_image);
+            self.draft_text = extraction.text.clone();
+            self.lines = extraction.lines.clone();
+            self.provenance = extraction.provenance.clone();
+            self.draft_page = Some(extraction.page.clone());
+            self.draft_appended = false;
+        }
+    }
+
+    fn text_changes_window(&mut self, ctx: &egui::Context) {
+        let Some((title, changes)) = &mut self.text_changes else {
+            return;
+        };
+
+        let mut open = true;
+        let mut apply = false;
+        egui::Window::new(*title).open(&mut open).show(ctx, |ui| {
+            if changes.is_empty() {
+                ui.label("No articles would change");
+            }
+            egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                for (i, change) in changes.iter_mut().enumerate() {
+                    ui.checkbox(&mut change.apply, format!("{} article {}", change.page, change.article));
+                    egui::CollapsingHeader::new("Old").id_salt(("change old", i)).show(ui, |ui| {
+                        ui.label(&change.old);
+                    });
+                    egui::CollapsingHeader::new("New").id_salt(("change new", i)).show(ui, |ui| {
+                        ui.label(&change.new);
+                    });
+                }
+            });
+            if ui.add_enabled(!self.readonly, egui::Button::new("Apply")).clicked() {
+                apply = true;
+            }
+        });
+
+        if apply {
+            let title = *title;
+            let applied: Vec<TextChange> = std::mem::take(changes).into_iter().filter(|change| change.apply).collect();
+            let (changed, pages, problems) = self.apply_text_changes(&applied, false);
+            self.last_text_changes = applied.clone();
+            self.status = format!("{}: changed {} articles", title, changed);
+            self.batch_summary = Some(BatchSummary { title, pages, changed, problems, undo: BatchUndo::TextChanges(applied) });
+        }
+        if apply || !open {
+            self.text_changes = None;
+        }
+    }
+
+    // Set each article's text to the new text, or back to the old text when undoing (only where
+    // it hasn't been edited since). Returns how many articles and pages were changed, and why
+    // any articles weren't
+    fn apply_text_changes(&mut self, changes: &[TextChange], undo: bool) -> (usize, usize, Vec<String>) {
+        let mut count = 0;
+        let mut pages = BTreeSet::new();
+        let mut problems = Vec::new();
+        for change in changes {
+            let (from, to) = if undo { (&change.new, &change.old) } else { (&change.old, &change.new) };
+            // Checkpoint each page once, before its first change
+            let current = self.state.pages.get(&change.page).and_then(|page| page.articles.iter().find(|article| article.id == change.article));
+            if current.is_some_and(|article| article.text == *from) && !pages.contains(&change.page) {
+                self.checkpoint_page(change.page.clone());
+            }
+            match self.state.pages.get_mut(&change.page).and_then(|page| page.article_mut(change.article)) {
+                Some(article) if article.text == *from => {
+                    article.text = to.clone();
+                    article.touch();
+                    count += 1;
+                    pages.insert(&change.page);
+                }
+                Some(_) => problems.push(format!("{} article {} has been edited since", change.page, change.article)),
+                None => problems.push(format!("{} article {} no longer exists", change.page, change.article)),
+            }
+        }
+        (count, pages.len(), problems)
+    }
+
+    fn batch_summary_window(&mut self, ctx: &egui::Context) {
+        let Some(summary) = &self.batch_summary else {
+            return;
+        };
+
+        enum Choice { Undo, Close }
+
+        let mut choice = None;
+        egui::Window::new(summary.title).collapsible(false).show(ctx, |ui| {
+            ui.label(match summary.undo {
+                BatchUndo::TextChanges(_) => format!("Changed {} articles on {} pages", summary.changed, summary.pages),
+                BatchUndo::Blanks(_) => format!("Marked {} pages as blank", summary.pages),
+            });
+            if !summary.problems.is_empty() {
+                ui.colored_label(Color32::YELLOW, format!("Skipped {}:", summary.problems.len()));
+                egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                    for problem in &summary.problems {
+                        ui.label(problem);
+                    }
+                });
+            }
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!self.readonly && summary.changed > 0, egui::Button::new("Undo all")).clicked() {
+                    choice = Some(Choice::Undo);
+                }
+                if ui.button("Close").clicked() {
+                    choice = Some(Choice::Close);
+                }
+            });
+        });
+
+        match choice {
+            Some(Choice::Undo) => {
+                match self.batch_summary.take().unwrap().undo {
+                    BatchUndo::TextChanges(changes) => {
+                        let (count, _, _) = self.apply_text_changes(&changes, true);
+                        self.last_text_changes.clear();
+                        self.status = format!("Restored {} articles", count);
+                    }
+                    BatchUndo::Blanks(filenames) => {
+                        for filename in &filenames {
+                            if let Some(page) = self.state.pages.get_mut(filename) {
+                                page.blank = false;
+                            }
+                        }
+                        self.status = format!("Unmarked {} blank pages", filenames.len());
+                    }
+                }
+            }
+            Some(Choice::Close) => self.batch_summary = None,
+            None => {}
+        }
+    }
+
+    // The articles a transform would apply to, with their pages
+    fn transform_articles(&self) -> Vec<(&String, &Article)> {
+        let last = self.state.images.len() - 1;
+        let (first, end) = (self.transform_pages.0.min(last), self.transform_pages.1.min(last));
+        self.state.images[first..=end.max(first)].iter()
+            .filter_map(|filename| Some((filename, self.state.pages.get(filename)?)))
+            .flat_map(|(filename, page)| page.articles.iter().map(move |article| (filename, article)))
+            .filter(|(_, article)| !self.transform_open_article || Some(article.id) == self.open_article)
+            .collect()
+    }
+
+    fn find_transforms(&self) -> Vec<TextChange> {
+        let mut changes = Vec::new();
+        for (filename, article) in self.transform_articles() {
+            let new = self.transform.apply(&article.text);
+            if new != article.text {
+                changes.push(TextChange { page: filename.clone(), article: article.id, old: article.text.clone(), new, apply: true });
+            }
+        }
+        changes
+    }
+
+    fn find_gremlins(&self) -> Vec<Gremlin> {
+        let mut gremlins = Vec::new();
+        for (filename, article) in self.transform_articles() {
+            for (i, line) in article.text.split('\n').enumerate() {
+                for (j, c) in line.chars().enumerate() {
+                    if let Some((problem, replacement)) = char_replacement(c) {
+                        gremlins.push(Gremlin { page: filename.clone(), article: article.id, line: i + 1, column: j + 1, c, problem, replacement });
+                    }
+                }
+            }
+        }
+        gremlins
+    }
+
+    fn gremlins_window(&mut self, ctx: &egui::Context) {
+        let Some(gremlins) = &self.gremlins else {
+            return;
+        };
+
+        let mut open = true;
+        let mut fix = false;
+        let mut goto = None;
+        egui::Window::new("Unusual characters").open(&mut open).show(ctx, |ui| {
+            if gremlins.is_empty() {
+                ui.label("No unusual characters found");
+            }
+            egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                for gremlin in gremlins {
+                    ui.horizontal(|ui| {
+                        let suggestion = match gremlin.replacement {
+                            "" => "delete it".to_owned(),
+                            replacement => format!("replace with {:?}", replacement),
+                        };
+                        ui.label(format!("{} article {} line {} column {}: U+{:04X} {}, {}",
+                            gremlin.page, gremlin.article, gremlin.line, gremlin.column, gremlin.c as u32, gremlin.problem, suggestion));
+                        if ui.button("Go").clicked() {
+                            goto = Some((gremlin.page.clone(), gremlin.article));
+                        }
+                    });
+                }
+            });
+            if ui.add_enabled(!self.readonly && !gremlins.is_empty(), egui::Button::new("Fix...")).on_hover_text("Preview the suggested fixes").clicked() {
+                fix = true;
+            }
+        });
+
+        if let Some((page, article)) = goto {
+            if let Some(index) = self.state.images.iter().position(|filename| *filename == page) {
+                if index == self.state.open_image {
+                    self.open_article = Some(article);
+                } else {
+                    // Opened once the page has loaded
+                    self.state.page_named(page).open_article = Some(article);
+                    self.goto_image(index);
+                }
+            }
+        }
+        if fix {
+            self.transform = Transform::FixCharacters;
+            self.text_changes = Some((self.transform.name(), self.find_transforms()));
+        }
+        if fix || !open {
+            self.gremlins = None;
+        }
+    }
+
+    fn leave_window(&mut self, ctx: &egui::Context) {
+        let Some((leave, dirty)) = self.leaving else {
+            return;
+        };
+
+        enum Choice { Save, Discard, Cancel }
+
+        let (title, question) = match leave {
+            Leave::Project(index) => ("Switch project", format!("Save {} before switching to {}?", self.project.name, self.projects.projects[index].name)),
+            Leave::Quit => ("Quit", format!("Save {} before quitting?", self.project.name)),
+        };
+        let mut choice = None;
+        egui::Window::new(title).collapsible(false).resizable(false).show(ctx, |ui| {
+            if dirty {
+                ui.label(question);
+            }
+            if self.unappended_draft() {
+                ui.label("The draft hasn't been appended to an article, and will be lost.");
+            }
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!self.readonly && dirty, egui::Button::new("Save")).clicked() {
+                    choice = Some(Choice::Save);
+                }
+                if ui.button(if dirty { "Don't save" } else { "Continue" }).clicked() {
+                    choice = Some(Choice::Discard);
+                }
+                if ui.button("Cancel").clicked() {
+                    choice = Some(Choice::Cancel);
+                }
+            });
+        });
+
+        match choice {
+            Some(Choice::Save) => {
+                self.save();
+                self.finish_leaving(ctx, leave);
+            }
+            Some(Choice::Discard) => self.finish_leaving(ctx, leave),
+            Some(Choice::Cancel) => self.leaving = None,
+            None => {}
+        }
+    }
+
+    fn projects_ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Projects").show(ui, |ui| {
+            let mut selected = self.projects.last_opened;
+            egui::ComboBox::from_label("Project")
+                .selected_text(&self.project.name)
+                .show_ui(ui, |ui| {
+                    for (i, project) in self.projects.projects.iter().enumerate() {
+                        ui.selectable_value(&mut selected, i, &project.name);
+                    }
+                });
+            if selected != self.projects.last_opened {
+                self.leave(ui.ctx(), Leave::Project(selected));
+            }
+
+            ui.label("New project");
+            egui::Grid::new("new project").show(ui, |ui| {
+                ui.label("Name");
+                ui.text_edit_singleline(&mut self.new_project.name);
+                ui.end_row();
+                ui.label("Annotations file");
+                ui.text_edit_singleline(&mut self.new_project.annotations);
+                ui.end_row();
+                ui.label("Images directory");
+                ui.text_edit_singleline(&mut self.new_project.images);
+                ui.end_row();
+            });
+            let valid = !self.new_project.name.is_empty() && !self.new_project.annotations.is_empty() && !self.new_project.images.is_empty();
+            if ui.add_enabled(valid, egui::Button::new("Add project")).clicked() {
+                let mut project = self.new_project.clone();
+                if !project.images.ends_with('/') {
+                    project.images.push('/');
+                }
+                self.projects.projects.push(project);
+                self.projects.save();
+                self.leave(ui.ctx(), Leave::Project(self.projects.projects.len() - 1));
+            }
+        });
+    }
+
+    fn unsaved_draft_window(&mut self, ctx: &egui::Context) {
+        let Some(index) = self.pending_navigation else {
+            return;
+        };
+
+        enum Choice { Append, Discard, Cancel }
+
+        let can_append = !self.readonly && self.open_article.is_some();
+        let mut choice = None;
+        egui::Window::new("Unsaved draft").collapsible(false).resizable(false).show(ctx, |ui| {
+            ui.label("The extracted text hasn't been appended to an article yet.");
+            ui.horizontal(|ui| {
+                if ui.add_enabled(can_append, egui::Button::new("Append")).clicked() {
+                    choice = Some(Choice::Append);
+                }
+                if ui.button("Discard").clicked() {
+                    choice = Some(Choice::Discard);
+                }
+                if ui.button("Cancel").clicked() {
+                    choice = Some(Choice::Cancel);
+                }
+            });
+        });
+
+        match choice {
+            Some(Choice::Append) => {
+                self.append_draft(Join::Line);
+                self.pending_navigation = None;
+                self.goto_image(index);
+            }
+            Some(Choice::Discard) => {
+                self.draft_text.clear();
+                self.draft_page = None;
+                self.pending_navigation = None;
+                self.goto_image(index);
+            }
+            Some(Choice::Cancel) => {
+                self.pending_navigation = None;
+            }
+            None => {}
+        }
+    }
+
+    fn popup(&mut self, ui: &mut egui::Ui) {
+        let draft_font = FontId::new(11.0, FontFamily::Monospace);
+
+        egui::Frame::none()
+            .fill(egui::Color32::BLACK)
+            .show(ui, |ui| {
+                ui.vertical(|ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Extract").on_hover_text("Run OCR on the poly (E)").clicked() {
+                            self.extract();
+                        }
+                        if ui.add_enabled(!self.readonly, egui::Button::new("Image")).on_hover_text("Save as an image region with a caption, without running OCR").clicked() {
+                            self.save_as_image();
+                        }
+                        if ui.add_enabled(self.drawing, egui::Button::new("Finish")).on_hover_text("Close the poly (Enter, or Shift+double-click the last point)").clicked() {
+                            self.finish_poly();
+                        }
+                        let can_hole = self.previous_vertexes.as_ref().is_some_and(|(outer, _)| outer.len() >= 3);
+                        if ui.add_enabled(can_hole, egui::Button::new("Hole")).on_hover_text("Mask this poly out of the previous one").clicked() {
+                            let (outer, mut holes) = self.previous_vertexes.take().unwrap();
+                            holes.push(std::mem::replace(&mut self.vertexes, outer));
+                            self.holes = holes;
+                        }
+                        if ui.add_enabled(self.copied_poly.is_some(), egui::Button::new("Paste poly")).on_hover_text("Use the last copied poly here (Ctrl+V)").clicked() {
+                            self.paste_poly(self.copied_poly.clone().unwrap());
+                        }
+                        if ui.button("Crop").on_hover_text("Preview the crop without running OCR").clicked() {
+                            self.extract_image();
+                        }
+                        if ui.button("Append").clicked() {
+                            self.append_draft(Join::Line);
+                        }
+                        let can_choose = !self.draft_text.is_empty() && !self.draft_appended;
+                        if ui.add_enabled(can_choose, egui::SelectableLabel::new(self.choosing_append_target, "Append to..."))
+                            .on_hover_text("Click an article to append to, instead of the open one").clicked() {
+                            self.choosing_append_target = !self.choosing_append_target;
+                        }
+                        if ui.add_enabled(!self.readonly, egui::Button::new("Scratch")).on_hover_text("Put the draft on the page's scratchpad, to sort into articles later").clicked() {
+                            self.draft_to_scratchpad();
+                        }
+                        if ui.button("Append P").clicked() {
+                            self.append_draft(Join::Paragraph);
+                        }
+                        if ui.button("Append with").on_hover_text("Join onto the end of the last line with this separator instead").clicked() {
+                            self.append_draft(Join::Custom(self.separator.clone()));
+                        }
+                        ui.add(egui::TextEdit::singleline(&mut self.separator).font(draft_font.clone()).desired_width(20.0));
+                        if ui.button("#").on_hover_text("Make the line with the cursor, or the selected lines, a heading").clicked() {
+                            self.draft_text = Self::make_heading(&self.draft_text, self.draft_cursor);
+                        }
+                        let headline = Self::detect_headline(&self.lines);
+                        let hover = match &headline {
+                            Some(range) => format!("Make the larger line(s) \"{}\" a # heading", self.lines[range.clone()].iter().map(|line| line.text.as_str()).collect::<Vec<_>>().join(" ")),
+                            None => "No line is noticeably larger than the rest".to_owned(),
+                        };
+                        if ui.add_enabled(headline.is_some(), egui::Button::new("# Headline")).on_hover_text(&hover).on_disabled_hover_text(&hover).clicked() {
+                            self.draft_text = self.merge_with_headline(headline.unwrap());
+                            self.draft_appended = false;
+                        }
+                        // if ui.button("##").clicked() {
+                        //     self.draft_text = self.draft_text.replace("\n", " ").trim().to_string() + "\n";
+                        //     self.draft_text.insert_str(0, "## ");
+                        // }
+                        if ui.button("Article").clicked() {
+                            self.new_article();
+                        }
+                        if ui.button("Article+Append").on_hover_text("Append to a new article, if none is open (N)").clicked() {
+                            self.append_to_new_article();
+                        }
+                    });
+
+                    if self.no_text_detected() {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(Color32::YELLOW, "No text detected in this region");
+                            if ui.add_enabled(!self.readonly, egui::Button::new("Save as image"))
+                                .on_hover_text("Add the region as an image article with a caption, for a photo or illustration")
+                                .clicked() {
+                                self.save_as_image();
+                            }
+                        });
+                    }
+                    // Even-odd filling alternates between the loops, which is rarely what was meant
+                    if !self.state.settings.nonzero_fill && geometry::self_intersects(&self.vertexes) {
+                        ui.colored_label(Color32::YELLOW, "The poly crosses itself, so alternate loops will be masked out (or fill by winding in Settings)");
+                    }
+                    let output = egui::TextEdit::multiline(&mut self.draft_text).font(draft_font.clone()).desired_width(400.0).show(ui);
+                    if let Some(readout) = Self::cursor_readout(&self.draft_text, &output) {
+                        ui.label(readout);
+                    }
+                    if let Some(range) = output.cursor_range.filter(|_| output.response.has_focus()) {
+                        let [start, end] = range.sorted_cursors();
+                        self.draft_cursor = (start.ccursor.index, end.ccursor.index);
+                    }
+                    // Only while the draft is exactly what was merged, or the offsets would be wrong
+                    if let Some(selection) = Self::selected_range(&self.draft_text, &output) {
+                        let (merged, offsets) = Self::merge_lines_with_offsets(&self.lines, self.crop_image.width() as f32, &self.state.settings);
+                        if merged == self.draft_text {
+                            self.selection_highlight = Self::selection_rects(&self.lines, &offsets, self.crop_rect, 0, &selection);
+                            // It's drawn under the popup, in the next frame
+                            ui.ctx().request_repaint();
+                        }
+                    }
+
+                    egui::CollapsingHeader::new("Paragraphs").show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            if ui.button("Split at short lines").on_hover_text("Start a paragraph after each short line that ends a sentence").clicked() {
+                                self.draft_text = Self::split_paragraphs(&self.draft_text, Some(self.state.settings.paragraph_short_line));
+                            }
+                            ui.add(egui::DragValue::new(&mut self.state.settings.paragraph_short_line).range(0.1..=1.0).speed(0.01).suffix(" of median"))
+                                .on_hover_text("Lines shorter than this fraction of the median line count as short. Higher splits more");
+                            if ui.button("Split at sentences").on_hover_text("Start a paragraph after every line that ends a sentence").clicked() {
+                                self.draft_text = Self::split_paragraphs(&self.draft_text, None);
+                            }
+                        });
+                    });
+
+                    if !self.lines.is_empty() {
+                        egui::CollapsingHeader::new(format!("Lines ({})", self.lines.len())).show(ui, |ui| {
+                            self.lines_ui(ui, &draft_font);
+                        });
+                    }
+
+                    if self.crop_rect.is_positive() {
+                        ui.checkbox(&mut self.crop_outline, "Outline on source");
+                        let retained = if self.crop_outline { &self.retained_source_crop } else { &self.retained_crop };
+                        let preview_scale = f32::min(400.0 / self.crop_rect.width(), 300.0 / self.crop_rect.height());
+                        let preview = ui.add(egui::Image::new((retained.texture_id(ui.ctx()), self.crop_rect.size() * preview_scale)));
+                        if self.crop_outline {
+                            let to_preview = |p: Pos2| preview.rect.min + (p - self.crop_rect.min) * preview_scale;
+                            ui.painter().add(PathShape::closed_line(
+                                self.vertexes.iter().map(|&p| to_preview(p)).collect(),
+                                Stroke::new(1.0, Color32::RED),
+                            ));
+                        }
+                        egui::CollapsingHeader::new("Levels").show(ui, |ui| {
+                            self.presets_ui(ui);
+                            self.levels_ui(ui);
+                        });
+                    }
+                });
+            });
+    }
+
+    // Insert, remove and reorder entries in the images list, for scans added or removed mid-project
+    fn images_ui(&mut self, ui: &mut egui::Ui) {
+        let open = self.state.open_image;
+        let count = self.state.images.len();
+        ui.add_enabled_ui(!self.readonly, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} of {}", open + 1, count));
+                if ui.add_enabled(open > 0, egui::Button::new("Move earlier")).clicked() {
+                    self.state.move_image(open, open - 1);
+                }
+                if ui.add_enabled(open + 1 < count, egui::Button::new("Move later")).clicked() {
+                    self.state.move_image(open, open + 1);
+                }
+                // There has to be an image open, and the draft belongs to this one
+                let can_remove = count > 1 && (self.draft_text.is_empty() || self.draft_appended);
+                if ui.add_enabled(can_remove, egui::Button::new("Remove")).on_hover_text("Remove this image from the list, keeping its annotations in the file").clicked() {
+                    self.remember_open_article();
+                    let filename = self.state.remove_image(open);
+                    self.status = format!("Removed {} from the images list", filename);
+                    // The poly was drawn on the removed page
+                    self.vertexes.clear();
+                    self.holes.clear();
+                    self.drawing = false;
+                    self.load_image_async();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.insert_image).desired_width(200.0).hint_text("filename"));
+                let filename = self.insert_image.trim().to_owned();
+                let valid = !filename.is_empty() && !self.state.images.contains(&filename);
+                if ui.add_enabled(valid, egui::Button::new("Insert after this")).clicked() {
+                    if !std::path::Path::new(&format!("{}{}", self.project.images, filename)).is_file() {
+                        self.status = format!("Inserted {}, but it isn't in {}", filename, self.project.images);
+                    } else if self.state.pages.contains_key(&filename) {
+                        self.status = format!("Inserted {}, with its existing annotations", filename);
+                    } else {
+                        self.status = format!("Inserted {}", filename);
+                    }
+                    self.state.insert_image(open + 1, filename);
+                    self.insert_image.clear();
+                }
+            });
+        });
+    }
+
+    // Every page with edits that can be undone, so a mistake can be fixed after moving on
+    fn undo_ui(&mut self, ui: &mut egui::Ui) {
+        if self.undo.is_empty() {
+            ui.label("No edits to undo");
+            return;
+        }
+        let mut pages: Vec<(usize, String, usize)> = self.undo.iter()
+            .map(|(filename, stack)| (self.state.images.iter().position(|f| f == filename).unwrap_or(usize::MAX), filename.clone(), stack.len()))
+            .collect();
+        pages.sort();
+        for (index, filename, count) in pages {
+            ui.horizontal(|ui| {
+                let open = index == self.state.open_image;
+                ui.label(format!("{}{}: {} edits", filename, if open { " (open)" } else { "" }, count));
+                if ui.add_enabled(!self.readonly, egui::Button::new("Undo")).on_hover_text("Ctrl+Z on the open page").clicked() {
+                    self.undo(&filename);
+                }
+                if ui.add_enabled(!open && index != usize::MAX, egui::Button::new("Go")).clicked() {
+                    self.goto_image(index);
+                }
+            });
+        }
+    }
+
+    // Recent log records, newest last, so errors can be seen without a terminal
+    fn log_ui(ui: &mut egui::Ui) {
+        let records = logging::records();
+        let errors = records.iter().filter(|record| record.level == log::Level::Error).count();
+        let title = if errors > 0 { format!("Log ({} errors)", errors) } else { "Log".to_owned() };
+        egui::CollapsingHeader::new(title).id_salt("log").show(ui, |ui| {
+            egui::ScrollArea::vertical().max_height(200.0).stick_to_bottom(true).show(ui, |ui| {
+                for record in records.iter() {
+                    let color = match record.level {
+                        log::Level::Error => Color32::RED,
+                        log::Level::Warn => Color32::YELLOW,
+                        _ => ui.visuals().text_color(),
+                    };
+                    ui.colored_label(color, format!("{} {}", record.level, record.message)).on_hover_text(format_age(record.time));
+                }
+            });
+            if ui.add_enabled(!records.is_empty(), egui::Button::new("Clear")).clicked() {
+                logging::clear();
+            }
+        });
+    }
+
+    // First and last image index, for exporting or transforming some pages
+    fn page_range_ui(ui: &mut egui::Ui, range: &mut (usize, usize), open_image: usize, last: usize) {
+        ui.horizontal(|ui| {
+            ui.label("Pages");
+            ui.add(egui::DragValue::new(&mut range.0).range(0..=last));
+            ui.label("to");
+            ui.add(egui::DragValue::new(&mut range.1).range(0..=last));
+            if ui.button("All").clicked() {
+                *range = (0, last);
+            }
+            if ui.button("This page").clicked() {
+                *range = (open_image, open_image);
+            }
+        });
+    }
+
+    // Join the lines from the one with char index start to the one with end into a single # heading,
+    // leaving the rest of the text as it is
+    fn make_heading(text: &str, (start, end): (usize, usize)) -> String {
+        let byte = |i: usize| text.char_indices().nth(i).map_or(text.len(), |(b, _)| b);
+        let (start, mut end) = (byte(start), byte(end));
+        // A selection of whole lines ends at the start of the next one
+        if end > start && text[..end].ends_with('\n') {
+            end -= 1;
+        }
+        let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+        let heading = text[line_start..line_end].split_whitespace().collect::<Vec<_>>().join(" ");
+        let rest = &text[line_end..];
+        format!("{}# {}{}", &text[..line_start], heading.trim_start_matches(['#', ' ']), if rest.is_empty() { "\n" } else { rest })
+    }
+
+    // Start a new paragraph after each line that ends a sentence, when the next starts like a new
+    // one. With short_line, only after lines shorter than that fraction of the median line, since
+    // the last line of a paragraph usually stops short. Existing paragraphs and line breaks are kept
+    fn split_paragraphs(text: &str, short_line: Option<f32>) -> String {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut lengths: Vec<usize> = lines.iter().map(|line| line.trim().chars().count()).filter(|&n| n > 0).collect();
+        lengths.sort();
+        let median = lengths.get(lengths.len() / 2).copied().unwrap_or(0) as f32;
+
+        let ends_sentence = |line: &str| {
+            line.trim_end().trim_end_matches(['"', '\'', '”', '’', ')', ']']).ends_with(['.', '!', '?'])
+        };
+        let starts_sentence = |line: &str| {
+            line.trim_start().chars().next().is_some_and(|c| c.is_uppercase() || c.is_ascii_digit() || "\"'“‘(".contains(c))
+        };
+        let mut out = String::new();
+        for (i, line) in lines.iter().enumerate() {
+            out.push_str(line);
+            out.push('\n');
+            let Some(next) = lines.get(i + 1) else {
+                continue;
+            };
+            let short = short_line.is_none_or(|ratio| (line.trim().chars().count() as f32) < median * ratio);
+            if !line.trim().is_empty() && !next.trim().is_empty() && ends_sentence(line) && starts_sentence(next) && short {
+                out.push('\n');
+            }
+        }
+        if !text.ends_with('\n') {
+            out.pop();
+        }
+        out
+    }
+
+    // "Ln 3, Col 12" for the cursor, plus the word count of any selection, while the editor has focus
+    fn cursor_readout(text: &str, output: &egui::text_edit::TextEditOutput) -> Option<String> {
+        if !output.response.has_focus() {
+            return None;
+        }
+        let range = output.cursor_range?;
+        let cursor = range.primary.ccursor.index;
+        let before: String = text.chars().take(cursor).collect();
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        let mut readout = format!("Ln {}, Col {}", line, column);
 
-        egui::Frame::none()
-            .fill(egui::Color32::BLACK)
-            .show(ui, |ui| {
-                ui.vertical(|ui| {
-                    ui.horizontal(|ui| {
-                        if ui.button("Extract").clicked() {
-                            let image = self.extract_image();
-                            (self.draft_text, self.crop_image) = self.runtime.block_on(self.extract_text(image));
+        let [start, end] = range.sorted_cursors();
+        if start.ccursor.index != end.ccursor.index {
+            let selected: String = text.chars().skip(start.ccursor.index).take(end.ccursor.index - start.ccursor.index).collect();
+            readout += &format!(" ({} words selected)", selected.split_whitespace().count());
+        }
+        Some(readout)
+    }
 
-                            let egui_image = ColorImage::from_rgb([self.crop_image.width() as _, self.crop_image.height() as _], self.crop_image.as_flat_samples().as_slice());
-                            self.retained_crop = RetainedImage::from_color_image("crop", egui_image);
-                        }
-                        let articles = &mut self.state.page().articles;
-                        if ui.button("Append").clicked() {
-                            if let Some(i) = self.open_article {
-                                articles[i].text.push_str(&self.draft_text.trim_end());
-                                articles[i].text.push_str("\n");
-                                articles[i].polys.push(self.vertexes.clone());
-                            }
-                        }
-                        if ui.button("Append P").clicked() {
-                            if let Some(i) = self.open_article {
-                                articles[i].text.push_str("\n");
-                                articles[i].text.push_str(&self.draft_text.trim_end());
-                                articles[i].text.push_str("\n");
-                                articles[i].polys.push(self.vertexes.clone());
-                            }
-                        }
-                        if ui.button("#").clicked() {
-                            self.draft_text = self.draft_text.replace("\n", " ").trim().to_string() + "\n";
-                            self.draft_text.insert_str(0, "# ");
-                        }
-                        // if ui.button("##").clicked() {
-                        //     self.draft_text = self.draft_text.replace("\n", " ").trim().to_string() + "\n";
-                        //     self.draft_text.insert_str(0, "## ");
-                        // }
-                        if ui.button("Article").clicked() {
-                            self.new_article();
-                        }
-                    });
+    // The OCR lines in reading order, to fix up before merging them into the draft
+    fn lines_ui(&mut self, ui: &mut egui::Ui, font: &FontId) {
+        enum Action {
+            Up(usize),
+            Down(usize),
+            Split(usize),
+            Remove(usize),
+        }
+        let mut action = None;
 
-                    // ui.image(self.retained_crop.texture_id(ctx), self.retained_crop.size_vec2() * scale * 0.5);
-                    ui.add(egui::TextEdit::multiline(&mut self.draft_text).font(draft_font.clone()).desired_width(400.0));
+        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+            let count = self.lines.len();
+            for (i, line) in self.lines.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(i > 0, egui::Button::new("^").small()).clicked() {
+                        action = Some(Action::Up(i));
+                    }
+                    if ui.add_enabled(i + 1 < count, egui::Button::new("v").small()).clicked() {
+                        action = Some(Action::Down(i));
+                    }
+                    let (label, hover) = match line.paragraph {
+                        None => ("·", "Paragraph break detected automatically"),
+                        Some(true) => ("¶", "Always start a paragraph here"),
+                        Some(false) => ("-", "Never start a paragraph here"),
+                    };
+                    if ui.add(egui::Button::new(label).small()).on_hover_text(hover).clicked() {
+                        line.paragraph = match line.paragraph {
+                            None => Some(true),
+                            Some(true) => Some(false),
+                            Some(false) => None,
+                        };
+                    }
+                    ui.add(egui::TextEdit::singleline(&mut line.text).font(font.clone()).desired_width(280.0));
+                    if ui.add_enabled(line.text.contains(' '), egui::Button::new("Split").small()).clicked() {
+                        action = Some(Action::Split(i));
+                    }
+                    if ui.add(egui::Button::new("x").small()).clicked() {
+                        action = Some(Action::Remove(i));
+                    }
                 });
-            });
+            }
+        });
+
+        match action {
+            Some(Action::Up(i)) => self.lines.swap(i - 1, i),
+            Some(Action::Down(i)) => self.lines.swap(i, i + 1),
+            Some(Action::Split(i)) => {
+                if let Some((first, second)) = Self::split_line(&self.lines[i]) {
+                    self.lines[i] = first;
+                    self.lines.insert(i + 1, second);
+                }
+            }
+            Some(Action::Remove(i)) => {
+                self.lines.remove(i);
+            }
+            None => {}
+        }
+
+        if ui.button("Merge").on_hover_text("Replace the draft with these lines").clicked() {
+            self.draft_text = Self::merge_lines(self.lines.clone(), self.crop_image.width() as f32, &self.state.settings);
+            self.draft_appended = false;
+        }
     }
 
     fn sidebar(&mut self, scaler: Scaler, ui: &mut egui::Ui) {
@@ -533,82 +4755,498 @@ impl MyApp {
         ui.vertical(|ui| {
             ui.horizontal(|ui| {
                 if ui.add_enabled(self.state.open_image > 0, egui::Button::new("<<")).clicked() {
-                    self.state.open_image = self.state.open_image.saturating_sub(10);
-                    self.open_article = None;
-                    self.load_image();
+                    self.goto_image(self.state.open_image.saturating_sub(10));
                 }
-                if ui.add_enabled(self.state.open_image > 0, egui::Button::new("<")).clicked() {
-                    self.state.open_image -= 1;
-                    self.open_article = None;
-                    self.load_image();
+                let previous = self.step_image(false);
+                if ui.add_enabled(previous.is_some(), egui::Button::new("<")).clicked() {
+                    self.goto_image(previous.unwrap());
                 }
-                let mut open_image = self.state.open_image.to_string();
-                if ui.add(egui::TextEdit::singleline(&mut open_image).desired_width(30.0)).changed() {
-                    if let Ok(open_image) = open_image.parse::<usize>() {
-                        self.state.open_image = open_image.clamp(0, self.state.images.len() - 1);
-                        self.open_article = None;
-                        self.load_image();
+                // Keep whatever was typed while it isn't a number, so it can be fixed,
+                // and put the real index back if it's left like that
+                let mut open_image = self.page_input.clone().unwrap_or_else(|| self.state.open_image.to_string());
+                let invalid = self.page_input.is_some();
+                let edit = egui::TextEdit::singleline(&mut open_image).desired_width(30.0).text_color_opt(invalid.then_some(Color32::RED));
+                let response = ui.add_enabled(!self.state.images.is_empty(), edit).on_hover_text("Page index");
+                if response.changed() {
+                    match open_image.trim().parse::<usize>() {
+                        Ok(index) => {
+                            self.page_input = None;
+                            self.goto_image(index.min(self.state.images.len().saturating_sub(1)));
+                        }
+                        Err(_) if open_image.trim().is_empty() => self.page_input = Some(open_image),
+                        Err(_) => {
+                            self.status = format!("\"{}\" isn't a page index", open_image.trim());
+                            self.page_input = Some(open_image);
+                        }
                     }
                 }
-                if ui.add_enabled(self.state.open_image + 1 < self.state.images.len(), egui::Button::new(">")).clicked() {
-                    self.state.open_image += 1;
-                    self.open_article = None;
-                    self.load_image();
+                if response.lost_focus() {
+                    self.page_input = None;
+                }
+                let next = self.step_image(true);
+                if ui.add_enabled(next.is_some(), egui::Button::new(">")).clicked() {
+                    self.goto_image(next.unwrap());
                 }
                 if ui.add_enabled(self.state.open_image + 1 < self.state.images.len(), egui::Button::new(">>")).clicked() {
-                    self.state.open_image = usize::min(self.state.images.len() - 1, self.state.open_image + 10);
-                    self.open_article = None;
-                    self.load_image();
+                    self.goto_image(usize::min(self.state.images.len() - 1, self.state.open_image + 10));
                 }
-                if ui.button("Save").clicked() {
+                if ui.add_enabled(!self.readonly, egui::Button::new("Save")).clicked() {
                     self.save();
                 }
-                if ui.button("New article").clicked() {
+                if ui.add_enabled(!self.readonly, egui::Button::new("New article")).clicked() {
                     self.new_article();
                 }
-                let can_delete = match self.open_article {
-                    Some(i) => self.state.page().articles[i].text.is_empty(),
+                let open_index = self.open_article.and_then(|id| self.state.page().article_index(id));
+                let can_delete = !self.readonly && match open_index {
+                    Some(i) => self.state.page().articles[i].text.is_empty() && self.state.page().articles[i].caption.is_none(),
                     None => false,
                 };
                 if ui.add_enabled(can_delete, egui::Button::new("Delete article")).clicked() {
-                    self.state.page().articles.remove(self.open_article.unwrap());
+                    self.checkpoint();
+                    self.state.page().articles.remove(open_index.unwrap());
                     self.open_article = None;
                 }
+                if ui.add_enabled(!self.readonly, egui::Button::new("Duplicates")).clicked() {
+                    self.duplicates = Some(self.find_duplicates());
+                }
+                if ui.add_enabled(!self.history.is_empty(), egui::Button::new("History")).clicked() {
+                    self.show_history = !self.show_history;
+                }
+                ui.checkbox(&mut self.readonly, "Read-only");
+                if ui.checkbox(&mut self.measuring, "Measure (D)").changed() {
+                    self.measure.clear();
+                }
+                ui.checkbox(&mut self.show_ruler, "Ruler");
+            });
+
+            ui.horizontal(|ui| {
+                ui.add_enabled(!self.readonly, egui::Checkbox::new(&mut self.state.page().blank, "Blank page"));
+                if self.likely_blank && !self.state.page().blank {
+                    ui.colored_label(Color32::YELLOW, "(looks blank)");
+                }
+                ui.checkbox(&mut self.skip_blanks, "Skip blank pages with < >");
+                ui.add_enabled(!self.readonly, egui::Checkbox::new(&mut self.fast_entry, "Fast entry"))
+                    .on_hover_text("For pages with a single clipping: drag a box, fix the text, then Ctrl+Enter to save and go to the next page");
             });
 
+            self.tags_ui(ui);
+
             ui.horizontal(|ui| {
                 ui.label("Date");
-                ui.text_edit_singleline(self.state.page().date.as_mut().unwrap());
+                ui.add_enabled(!self.readonly, egui::TextEdit::singleline(self.state.page().date.as_mut().unwrap()));
             });
 
             ui.horizontal(|ui| {
                 ui.label("Summary");
-                ui.text_edit_singleline(self.state.page().summary.as_mut().unwrap());
+                ui.add_enabled(!self.readonly, egui::TextEdit::multiline(self.state.page().summary.as_mut().unwrap()).desired_rows(3));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Notes");
+                ui.add_enabled(!self.readonly, egui::TextEdit::multiline(&mut self.state.page().notes).desired_rows(2));
+            });
+
+            self.projects_ui(ui);
+
+            egui::CollapsingHeader::new("Export").show(ui, |ui| {
+                let last = self.state.images.len() - 1;
+                Self::page_range_ui(ui, &mut self.export_pages, self.state.open_image, last);
+                let (first, end) = (self.export_pages.0.min(last), self.export_pages.1.min(last));
+                let images: Vec<String> = self.state.images[first..=end.max(first)].iter().filter(|filename| self.page_included(filename)).cloned().collect();
+                let images = &images[..];
+
+                ui.horizontal(|ui| {
+                    if ui.button("ALTO").clicked() {
+                        let dir = self.project.export_path("-alto");
+                        self.status = match export::export_alto(&self.state, images, &self.project.images, &dir) {
+                            Ok(count) => format!("Exported {} pages to {}", count, dir),
+                            Err(err) => {
+                                log::error!("Export failed: {}", err);
+                                format!("Export failed: {}", err)
+                            }
+                        };
+                    }
+                    if ui.button("PDF").clicked() {
+                        let path = self.project.export_path(".pdf");
+                        self.status = match export::export_pdf(&self.state, images, &self.project.images, &path) {
+                            Ok(count) => format!("Exported {} pages to {}", count, path),
+                            Err(err) => {
+                                log::error!("Export failed: {}", err);
+                                format!("Export failed: {}", err)
+                            }
+                        };
+                    }
+                    if ui.button("YAML").clicked() {
+                        let path = self.project.export_path("-export.yaml");
+                        self.status = match schema::export_yaml(&self.state, images, &path) {
+                            Ok(count) => format!("Exported {} pages to {}", count, path),
+                            Err(err) => {
+                                log::error!("Export failed: {}", err);
+                                format!("Export failed: {}", err)
+                            }
+                        };
+                    }
+                    if ui.button("JSON lines").on_hover_text("One page per line, in the same format as YAML").clicked() {
+                        let path = self.project.export_path(".jsonl");
+                        self.status = match schema::export_jsonl(&self.state, images, &path) {
+                            Ok(count) => format!("Exported {} pages to {}", count, path),
+                            Err(err) => {
+                                log::error!("Export failed: {}", err);
+                                format!("Export failed: {}", err)
+                            }
+                        };
+                    }
+                    if ui.button("Word index").clicked() {
+                        let (words, names) = (self.project.export_path("-words.txt"), self.project.export_path("-names.txt"));
+                        self.status = match export::export_word_index(&self.state, images, &words, &names) {
+                            Ok(()) => format!("Exported {} and {}", words, names),
+                            Err(err) => {
+                                log::error!("Export failed: {}", err);
+                                format!("Export failed: {}", err)
+                            }
+                        };
+                    }
+                });
+            });
+
+            egui::CollapsingHeader::new("Transform").show(ui, |ui| {
+                Self::page_range_ui(ui, &mut self.transform_pages, self.state.open_image, self.state.images.len() - 1);
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("transform")
+                        .selected_text(self.transform.name())
+                        .show_ui(ui, |ui| {
+                            for transform in Transform::ALL {
+                                ui.selectable_value(&mut self.transform, transform, transform.name());
+                            }
+                        });
+                    ui.checkbox(&mut self.transform_open_article, "Open article only");
+                });
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!self.readonly, egui::Button::new("Preview")).clicked() {
+                        self.text_changes = Some((self.transform.name(), self.find_transforms()));
+                    }
+                    if ui.button("Check characters").on_hover_text("List invisible or unusual characters from OCR, with suggested fixes").clicked() {
+                        self.gremlins = Some(self.find_gremlins());
+                    }
+                    let can_undo = !self.readonly && !self.last_text_changes.is_empty();
+                    if ui.add_enabled(can_undo, egui::Button::new("Undo last")).on_hover_text("Undo the last transform or re-merge").clicked() {
+                        let changes = std::mem::take(&mut self.last_text_changes);
+                        let (count, _, _) = self.apply_text_changes(&changes, true);
+                        self.status = format!("Restored {} articles", count);
+                        self.batch_summary = None;
+                    }
+                });
+            });
+
+            egui::CollapsingHeader::new("Images").show(ui, |ui| {
+                self.images_ui(ui);
+            });
+
+            egui::CollapsingHeader::new("Batch").show(ui, |ui| {
+                ui.label("Run with --batch to extract every article that has polys but no text");
+                if ui.button("Dry run").on_hover_text("Count the OCR calls and find problem regions, without calling OCR").clicked() {
+                    self.batch_estimate = Some(self.estimate_batch());
+                }
+                if let Some(estimate) = &self.batch_estimate {
+                    egui::ScrollArea::vertical().id_salt("batch estimate").max_height(200.0).show(ui, |ui| {
+                        for line in estimate.summary() {
+                            ui.label(line);
+                        }
+                    });
+                }
+            });
+
+            let response = egui::CollapsingHeader::new("Statistics").show(ui, |ui| {
+                if ui.button("Refresh").clicked() {
+                    self.statistics = None;
+                }
+                let stats = self.statistics.take().unwrap_or_else(|| self.statistics());
+                for line in stats.summary() {
+                    ui.label(line);
+                }
+                self.statistics = Some(stats);
+            });
+            // Recomputed each time it's opened, rather than every frame while it's open
+            if response.fully_closed() {
+                self.statistics = None;
+            }
+
+            egui::CollapsingHeader::new(format!("Scratchpad ({})", self.state.page().scratchpad.len())).id_salt("scratchpad").show(ui, |ui| {
+                self.scratchpad_ui(ui);
+            });
+
+            egui::CollapsingHeader::new("Undo").show(ui, |ui| {
+                self.undo_ui(ui);
+            });
+
+            egui::CollapsingHeader::new("Settings").show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("UI scale (all projects)");
+                    let response = ui.add(egui::Slider::new(&mut self.projects.pixels_per_point, MIN_PIXELS_PER_POINT..=MAX_PIXELS_PER_POINT).step_by(0.25));
+                    // Only once the slider is let go, or the UI would change size under the pointer
+                    if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                        ui.ctx().set_pixels_per_point(self.projects.pixels_per_point);
+                        self.projects.save();
+                    }
+                });
+                if ui.checkbox(&mut self.projects.auto_save, "Save automatically when switching projects or quitting (all projects)").changed() {
+                    self.projects.save();
+                }
+                let ocr = &mut self.projects.ocr;
+                let mut ocr_changed = false;
+                egui::ComboBox::from_label("OCR (all projects)")
+                    .selected_text(ocr.backend.label())
+                    .show_ui(ui, |ui| {
+                        for backend in OcrBackend::ALL {
+                            ocr_changed |= ui.selectable_value(&mut ocr.backend, backend, backend.label()).changed();
+                        }
+                    });
+                match ocr.backend {
+                    OcrBackend::Textract => {
+                        ui.horizontal(|ui| {
+                            ui.label("Region");
+                            ocr_changed |= ui.add(egui::TextEdit::singleline(&mut ocr.textract.region).desired_width(90.0).hint_text("AWS default")).lost_focus();
+                            ui.label("Profile");
+                            ocr_changed |= ui.add(egui::TextEdit::singleline(&mut ocr.textract.profile).desired_width(90.0).hint_text("default"))
+                                .on_hover_text("Named profile in the AWS credentials file")
+                                .lost_focus();
+                        });
+                    }
+                }
+                if ocr_changed {
+                    self.projects.save();
+                }
+                let settings = &mut self.state.settings;
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut settings.simplify_polys, "Simplify polys on append, tolerance");
+                    ui.add(egui::DragValue::new(&mut settings.simplify_tolerance).range(0.0..=50.0).speed(0.1).suffix("px"));
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.state.settings.store_raw_ocr, "Store raw OCR");
+                    if ui.add_enabled(!self.readonly, egui::Button::new("Re-merge page")).clicked() {
+                        self.text_changes = Some(("Re-merge OCR", self.find_remerges(false)));
+                    }
+                    if ui.add_enabled(!self.readonly, egui::Button::new("Re-merge all")).clicked() {
+                        self.text_changes = Some(("Re-merge OCR", self.find_remerges(true)));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.state.settings.auto_verify, "Auto-verify when all lines are above");
+                    ui.add(egui::DragValue::new(&mut self.state.settings.auto_verify_confidence).range(0.0..=100.0).speed(0.1).suffix("%"));
+                });
+                ui.checkbox(&mut self.state.settings.guard_clear, "Need Ctrl+click to discard a poly with more than 3 points");
+                ui.checkbox(&mut self.state.settings.integer_polys, "Round polys to whole pixels when saving");
+                ui.checkbox(&mut self.state.settings.join_split_lines, "Join lines that OCR split side by side");
+                ui.checkbox(&mut self.state.settings.nonzero_fill, "Fill self-intersecting polys by winding, instead of alternating");
+                ui.checkbox(&mut self.state.settings.save_image_crops, "Save a PNG of each image article's region, next to the annotations");
+                ui.checkbox(&mut self.state.settings.antialias_mask, "Anti-alias the mask's edges")
+                    .on_hover_text(format!("Smoother crop edges for OCR, but the mask takes {} times as long", MASK_SAMPLES * MASK_SAMPLES));
+                ui.checkbox(&mut self.state.settings.preview_closing_edge, "Show where a poly will close while drawing it");
+                ui.checkbox(&mut self.state.settings.cross_fade, "Cross-fade between images");
+                ui.checkbox(&mut self.state.settings.confidence_tint, "Colour polys by OCR confidence");
+                ui.horizontal(|ui| {
+                    ui.label("Key to hide the sidebar");
+                    ui.add(egui::TextEdit::singleline(&mut self.state.settings.hide_chrome_key).desired_width(60.0));
+                    if egui::Key::from_name(&self.state.settings.hide_chrome_key).is_none() {
+                        ui.colored_label(Color32::RED, "unknown key");
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Scan resolution");
+                    ui.add_enabled(!self.readonly, egui::DragValue::new(&mut self.state.settings.dpi).range(0.0..=4800.0).speed(1.0).suffix(" dpi"))
+                        .on_hover_text("For the ruler and measurements in cm and inches; 0 if unknown");
+                });
+                ui.horizontal(|ui| {
+                    let settings = &mut self.state.settings;
+                    ui.label("Vertex radius");
+                    ui.add(egui::DragValue::new(&mut settings.vertex_radius).range(1.0..=50.0).speed(0.1).suffix("px"));
+                    ui.checkbox(&mut settings.vertex_radius_image_space, "in image space");
+                });
+                ui.horizontal(|ui| {
+                    let settings = &mut self.state.settings;
+                    ui.checkbox(&mut settings.line_sort_fudge, "Order lines at the same height by x, divisor");
+                    ui.add_enabled(settings.line_sort_fudge, egui::DragValue::new(&mut settings.line_sort_divisor).range(1.0..=1000.0).speed(0.5));
+                    ui.add_enabled(settings.line_sort_fudge || settings.row_clustering, egui::Checkbox::new(&mut settings.right_to_left, "Right to left"));
+                });
+                ui.horizontal(|ui| {
+                    let settings = &mut self.state.settings;
+                    ui.checkbox(&mut settings.row_clustering, "Order lines in rows instead, within")
+                        .on_hover_text("Lines whose centres are closer than this fraction of the median line height are in the same row");
+                    ui.add_enabled(settings.row_clustering, egui::DragValue::new(&mut settings.row_threshold).range(0.05..=2.0).speed(0.01).suffix("× line height"));
+                });
+                ui.horizontal(|ui| {
+                    let settings = &mut self.state.settings;
+                    ui.label("Paragraph indent");
+                    ui.add(egui::DragValue::new(&mut settings.paragraph_indent_min).range(0.0..=settings.paragraph_indent_max).speed(0.5).suffix("px"));
+                    ui.label("to");
+                    ui.add(egui::DragValue::new(&mut settings.paragraph_indent_max).range(settings.paragraph_indent_min..=500.0).speed(0.5).suffix("px"));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Mask fill");
+                    // Picking a fill here replaces any preset's that's being tried
+                    let mut fill = self.mask_fill();
+                    if ui.color_edit_button_srgb(&mut fill).changed() {
+                        self.state.settings.mask_fill = fill;
+                        self.preset_fill = None;
+                    }
+                    ui.label("Language");
+                    ui.add(egui::TextEdit::singleline(&mut self.state.settings.language).desired_width(60.0).hint_text("e.g. en-GB"));
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Save as project defaults").on_hover_text("Use this language, reading direction, mask fill and paragraph indent whenever the project is opened").clicked() {
+                        self.project.layout = Some(Layout::from_settings(&self.state.settings));
+                        self.projects.projects[self.projects.last_opened].layout = self.project.layout.clone();
+                        self.projects.save();
+                        self.status = format!("Saved the layout defaults for {}", self.project.name);
+                    }
+                    if ui.add_enabled(self.project.layout.is_some(), egui::Button::new("Forget")).clicked() {
+                        self.project.layout = None;
+                        self.projects.projects[self.projects.last_opened].layout = None;
+                        self.projects.save();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_label("Image order")
+                        .selected_text(format!("{:?}", self.state.settings.image_order))
+                        .show_ui(ui, |ui| {
+                            for order in [ImageOrder::Lexical, ImageOrder::Natural, ImageOrder::Modified] {
+                                ui.selectable_value(&mut self.state.settings.image_order, order, format!("{:?}", order));
+                            }
+                        });
+                    if ui.add_enabled(!self.readonly, egui::Button::new("Sort images")).clicked() {
+                        self.state.sort_images(&self.project.images);
+                    }
+                    if ui.add_enabled(!self.readonly && self.blank_detection.is_none(), egui::Button::new("Detect blanks")).on_hover_text("Mark unannotated pages that look blank").clicked() {
+                        self.detect_blank_pages();
+                    }
+                    if ui.add_enabled(!self.readonly, egui::Button::new("Scan folder")).clicked() {
+                        self.status = match self.state.scan_images(&self.project.images) {
+                            Ok(added) => format!("Added {} images from {}", added, self.project.images),
+                            Err(err) => format!("Failed to scan {}: {}", self.project.images, err),
+                        };
+                    }
+                });
             });
 
-            egui::ScrollArea::vertical().show(ui, |ui| {
+            Self::log_ui(ui);
+
+            if !self.status.is_empty() {
+                ui.label(&self.status);
+            }
+
+            ui.checkbox(&mut self.recent_first, "Most recently modified first");
+
+            let readonly = self.readonly;
+            let reorderable = !readonly && !self.recent_first;
+            egui::ScrollArea::vertical().auto_shrink(false).show_viewport(ui, |ui, viewport| {
                 let mut insert_note = None;
-                for (article_id, article) in self.state.page().articles.iter_mut().enumerate() {
+                let mut move_down = None;
+                let mut append_to = None;
+                let mut selection = None;
+                let mut edited = None; // an article as it was before this frame's edit to it
+                let mut copy = None;
+                let mut external = None; // article to open in an external editor
+                let count = self.state.page().articles.len();
 
-                    if ui.button("+N").clicked() {
-                        insert_note = Some(article_id);
+                let mut order: Vec<usize> = (0..count).collect();
+                if self.recent_first {
+                    let articles = &self.state.page().articles;
+                    order.sort_by_key(|&i| std::cmp::Reverse(articles[i].modified_at));
+                }
+
+                // Only lay out the articles that are visible, and leave space for the rest based on
+                // how tall they were when we last saw them
+                let spacing = ui.spacing().item_spacing.y;
+                let mut y = 0.0;
+                let mut skipped = 0.0;
+                for i in order {
+                    let article = &mut self.state.page().articles[i];
+                    let height = self.article_heights.get(&article.id).copied().unwrap_or(ARTICLE_ROW_HEIGHT);
+                    if y + height < viewport.min.y || y > viewport.max.y {
+                        skipped += height;
+                        y += height;
+                        continue;
                     }
+                    if skipped > 0.0 {
+                        ui.allocate_space(Vec2::new(0.0, (skipped - spacing).max(0.0)));
+                        skipped = 0.0;
+                    }
+                    let top = ui.cursor().top();
+
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(reorderable, egui::Button::new("+N")).clicked() {
+                            insert_note = Some(i);
+                        }
+                        if ui.add_enabled(reorderable && i > 0, egui::Button::new("^")).clicked() {
+                            move_down = Some(i - 1);
+                        }
+                        if ui.add_enabled(reorderable && i + 1 < count, egui::Button::new("v")).clicked() {
+                            move_down = Some(i);
+                        }
+                    });
 
                     let res = egui::CollapsingHeader::new(format!(
-                        "({}) {}...",
-                        article_id,
-                        article.text.replace("\n", " ").chars().take(40).collect::<String>()
+                        "({}){} {}...",
+                        i,
+                        if article.verified { " ✔" } else { "" },
+                        match article.kind {
+                            ArticleKind::Text => article.text.replace("\n", " ").chars().take(40).collect::<String>(),
+                            ArticleKind::Image => format!("[Image] {}", article.caption.as_deref().unwrap_or("").replace("\n", " ").chars().take(32).collect::<String>()),
+                        }
                     ))
-                    .id_salt(("article", article_id))
-                    .open(Some(self.open_article == Some(article_id)))
+                    .id_salt(("article", article.id))
+                    .open(Some(self.open_article == Some(article.id)))
                     .show(ui, |ui| {
+                        if ui.add_enabled(!readonly, egui::Checkbox::new(&mut article.verified, "Verified")).changed() {
+                            let mut before = article.clone();
+                            before.verified = !article.verified;
+                            edited = Some(before);
+                            article.touch();
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("Source");
+                            let mut source = article.source.clone().unwrap_or_default();
+                            let response = ui.add_enabled(!readonly, egui::TextEdit::singleline(&mut source).hint_text("newspaper, edition"));
+                            if response.gained_focus() {
+                                self.editing_text = Some(article.clone());
+                            }
+                            if response.changed() {
+                                if let Some(before) = self.editing_text.take_if(|before| before.id == article.id) {
+                                    edited = Some(before);
+                                }
+                                // Trimmed only once it loses focus, or spaces couldn't be typed between words
+                                article.source = Some(source).filter(|source| !source.is_empty());
+                                article.touch();
+                            }
+                            if response.lost_focus() {
+                                article.source = article.source.as_deref().map(str::trim).filter(|source| !source.is_empty()).map(str::to_owned);
+                            }
+                        });
                         let mut del = None;
                         for (i, vertexes) in article.polys.iter().enumerate() {
                             ui.horizontal(|ui| {
-                                if ui.button("-").clicked() {
+                                if ui.add_enabled(!readonly, egui::Button::new("-")).clicked() {
                                     del = Some(i);
                                 }
-                                if ui.label(format!("{:?}", vertexes)).hovered() {
+                                if ui.button("Copy").on_hover_text("Copy this poly's shape to paste elsewhere (Ctrl+V)").clicked() {
+                                    copy = Some(CopiedPoly { vertexes: vertexes.clone(), holes: article.holes.get(i).cloned().unwrap_or_default() });
+                                }
+                                let selected = self.selected_poly == Some((article.id, i));
+                                let label = ui.selectable_label(selected, format!("{:?}", vertexes))
+                                    .on_hover_text("Up and Down to choose a poly, and Delete to remove it");
+                                if label.clicked() {
+                                    self.selected_poly = if selected { None } else { Some((article.id, i)) };
+                                }
+                                if selected {
+                                    let path = PathShape {
+                                        points: vertexes.iter().map(|&p| scaler.image_to_screen(p)).collect(),
+                                        closed: true,
+                                        fill: Color32::TRANSPARENT,
+                                        stroke: PathStroke::new(3.0, Color32::from_rgba_unmultiplied(0, 255, 0, 255))
+                                    };
+                                    ui.painter().add(path);
+                                }
+                                if label.hovered() {
                                     let path = PathShape {
                                         points: vertexes.iter().map(|&p| scaler.image_to_screen(p)).collect(),
                                         closed: true,
@@ -620,30 +5258,363 @@ impl MyApp {
                             });
                         }
                         if let Some(d) = del {
-                            article.polys.remove(d);
+                            edited = Some(article.clone());
+                            article.remove_poly(d);
+                        }
+                        // Image articles have a caption instead of text
+                        if article.kind == ArticleKind::Image {
+                            ui.label("Caption");
+                            let mut caption = article.caption.clone().unwrap_or_default();
+                            let response = ui.add_enabled(!readonly, egui::TextEdit::multiline(&mut caption).font(article_font.clone()).desired_rows(2).hint_text("what the picture shows"));
+                            if response.gained_focus() {
+                                self.editing_text = Some(article.clone());
+                            }
+                            if response.changed() {
+                                if let Some(before) = self.editing_text.take_if(|before| before.id == article.id) {
+                                    edited = Some(before);
+                                }
+                                article.caption = Some(caption).filter(|caption| !caption.trim().is_empty());
+                                article.touch();
+                            }
+                            if let Some(crop) = &article.crop {
+                                ui.label(format!("Saved as {}", crop));
+                            }
+                            return;
+                        }
+                        let output = egui::TextEdit::multiline(&mut article.text).font(article_font.clone()).interactive(!readonly).show(ui);
+                        // Nothing's been typed yet in the frame it gets focus
+                        if output.response.gained_focus() {
+                            self.editing_text = Some(article.clone());
+                        }
+                        // One undo step for everything typed while it has focus
+                        if output.response.changed() {
+                            if let Some(before) = self.editing_text.take_if(|before| before.id == article.id) {
+                                edited = Some(before);
+                            }
+                            article.touch();
+                        }
+                        ui.horizontal(|ui| {
+                            if let Some(readout) = Self::cursor_readout(&article.text, &output) {
+                                ui.label(readout);
+                            }
+                            if self.external_edit.as_ref().is_some_and(|edit| edit.article == article.id) {
+                                ui.label("Open in editor");
+                            } else if ui.add_enabled(!readonly && self.external_edit.is_none(), egui::Button::new("Edit externally"))
+                                .on_hover_text("Open the text in $VISUAL or $EDITOR, and read it back whenever it's saved there")
+                                .clicked() {
+                                external = Some(article.id);
+                            }
+                        });
+                        if let Some(range) = Self::selected_range(&article.text, &output) {
+                            selection = Some((article.id, range));
                         }
-                        ui.add(egui::TextEdit::multiline(&mut article.text).font(article_font.clone()));
                     });
 
-                    if res.header_response.clicked() {
-                        if self.open_article == Some(article_id) {
+                    let mut hover = match (article.created_at, article.modified_at) {
+                        (Some(created), Some(modified)) => format!("Created {}, modified {}", format_age(created), format_age(modified)),
+                        (None, Some(modified)) => format!("Modified {}", format_age(modified)),
+                        _ => String::new(),
+                    };
+                    for (i, provenance) in article.provenance.iter().enumerate() {
+                        if let Some(provenance) = provenance {
+                            hover += &format!("\nPoly {}: {} {} ({})", i, provenance.backend, format_age(provenance.at), provenance.settings);
+                        }
+                    }
+                    let header = if hover.is_empty() { res.header_response } else { res.header_response.on_hover_text(hover.trim_start()) };
+                    if header.clicked() && self.choosing_append_target {
+                        append_to = Some(article.id);
+                    } else if header.clicked() {
+                        if self.open_article == Some(article.id) {
                             self.open_article = None;
                         } else {
-                            self.open_article = Some(article_id);
+                            self.open_article = Some(article.id);
                         }
                     }
+
+                    let height = ui.cursor().top() - top;
+                    self.article_heights.insert(article.id, height);
+                    y += height;
+                }
+                if skipped > 0.0 {
+                    ui.allocate_space(Vec2::new(0.0, (skipped - spacing).max(0.0)));
                 }
 
-                if let Some(article_id) = insert_note {
-                    self.state.page().articles.insert(article_id, Article {
-                        polys: Vec::new(),
-                        text: String::from("[NOTE] "),
-                    });
-                    self.open_article = Some(article_id);
+                if let Some(before) = edited {
+                    self.checkpoint_article(before);
+                }
+
+                if let Some(poly) = copy {
+                    self.copy_poly(ui.ctx(), poly);
+                }
+
+                if let Some(id) = external {
+                    self.open_in_editor(id);
+                }
+
+                if let Some(i) = move_down {
+                    self.checkpoint();
+                    self.state.page().articles.swap(i, i + 1);
                 }
 
-                ui.allocate_space(ui.available_size());
+                if let Some(id) = append_to {
+                    self.append_to_article(id);
+                }
+
+                if let Some((id, range)) = selection {
+                    let page = &self.state.pages[&self.state.images[self.state.open_image]];
+                    if let Some(rects) = page.articles.iter().find(|article| article.id == id).and_then(|article| article.selection_rects(&self.state.settings, &range)) {
+                        self.selection_highlight = rects;
+                        ui.ctx().request_repaint();
+                    }
+                }
+
+                if let Some(i) = insert_note {
+                    self.checkpoint();
+                    let id = self.state.new_article_id();
+                    self.state.page().articles.insert(i, Article::new(id, String::from("[NOTE] ")));
+                    self.open_article = Some(id);
+                }
             });
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with_poly(vertexes: Vec<Pos2>) -> State {
+        let mut article = Article::new(1, "Text\n".to_owned());
+        article.push_poly(vertexes, vec![vec![Pos2::new(2.4, 2.6), Pos2::new(3.5, 2.5), Pos2::new(3.0, 3.49)]], None, None);
+        let page = Page { date: Some(String::new()), summary: Some(String::new()), articles: vec![article], notes: String::new(), open_article: None, blank: false, tags: Vec::new(), scratchpad: Vec::new() };
+        State {
+            images: vec!["page1.jpg".to_owned()],
+            pages: BTreeMap::from([("page1.jpg".to_owned(), page)]),
+            open_image: 0,
+            settings: Settings::default(),
+            next_article_id: 2,
+        }
+    }
+
+    #[test]
+    fn round_polys() {
+        let mut state = state_with_poly(vec![Pos2::new(10.4, 20.6), Pos2::new(-0.4, 100.5), Pos2::new(1.0 / 3.0, 7.0)]);
+        state.round_polys();
+        let article = &state.pages["page1.jpg"].articles[0];
+        assert_eq!(article.polys[0], vec![Pos2::new(10.0, 21.0), Pos2::new(-0.0, 101.0), Pos2::new(0.0, 7.0)]);
+        assert_eq!(article.holes[0][0], vec![Pos2::new(2.0, 3.0), Pos2::new(4.0, 3.0), Pos2::new(3.0, 3.0)]);
+    }
+
+    #[test]
+    fn rounded_polys_round_trip() {
+        let mut state = state_with_poly(vec![Pos2::new(123.456, 789.012), Pos2::new(0.1 + 0.2, 5e-7), Pos2::new(4000.49, 2999.51)]);
+        state.round_polys();
+        let saved = serde_yaml::to_string(&state).unwrap();
+
+        // Loading and saving again doesn't change anything, and rounding again doesn't either
+        let mut loaded: State = serde_yaml::from_str(&saved).unwrap();
+        assert_eq!(serde_yaml::to_string(&loaded).unwrap(), saved);
+        loaded.round_polys();
+        assert_eq!(serde_yaml::to_string(&loaded).unwrap(), saved);
+        assert_eq!(loaded.pages["page1.jpg"].articles[0].polys[0], vec![Pos2::new(123.0, 789.0), Pos2::new(0.0, 0.0), Pos2::new(4000.0, 3000.0)]);
+    }
+
+    // Loading a saved file and saving it again, or just looking at another page, gives the same bytes
+    #[test]
+    fn save_is_stable() {
+        let mut state = state_with_poly(vec![Pos2::new(0.1 + 0.2, 1.0 / 3.0), Pos2::new(-0.0, 1e-7), Pos2::new(12345.678, 9.99999)]);
+        state.images.push("page2.jpg".to_owned());
+        let article = &mut state.page_named("page1.jpg".to_owned()).articles[0];
+        article.raw_ocr[0] = Some(RawOcr {
+            width: 123.456,
+            crop: Some(Rect::from_min_max(Pos2::new(0.3, 0.0), Pos2::new(12345.7, 10.0))),
+            paragraph: true,
+            separator: None,
+            lines: vec![Line {
+                text: "Text".to_owned(),
+                points: vec![Vec2::new(0.1, 0.2), Vec2::new(1.0 / 7.0, 0.9)],
+                bbox: Rect::from_min_max(Pos2::new(0.1, 0.2), Pos2::new(1.0 / 7.0, 0.9)),
+                left: 0.1,
+                mid: Vec2::new(0.12, 0.55),
+                confidence: 99.87654,
+                paragraph: Some(false),
+            }],
+        });
+        state.page_named("page1.jpg".to_owned()).tags.push("sports".to_owned());
+        let saved = serde_yaml::to_string(&state).unwrap();
+
+        let path = std::env::temp_dir().join(format!("scrapbook-annotate-test-{}.yaml", std::process::id()));
+        std::fs::write(&path, &saved).unwrap();
+        let mut loaded = State::load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(serde_yaml::to_string(&loaded).unwrap(), saved);
+
+        loaded.page_named("page2.jpg".to_owned());
+        assert_eq!(serde_yaml::to_string(&loaded).unwrap(), saved);
+        assert!(!saved.contains("page2.jpg:"), "{}", saved);
+    }
+
+    // A line at the given fraction-of-crop coordinates
+    fn line(text: &str, left: f32, top: f32, right: f32, bottom: f32) -> Line {
+        let bbox = Rect::from_min_max(Pos2::new(left, top), Pos2::new(right, bottom));
+        Line {
+            text: text.to_owned(),
+            points: vec![bbox.left_top().to_vec2(), bbox.right_top().to_vec2(), bbox.right_bottom().to_vec2(), bbox.left_bottom().to_vec2()],
+            bbox,
+            left,
+            mid: bbox.center().to_vec2(),
+            confidence: 90.0,
+            paragraph: None,
+        }
+    }
+
+    fn joined_texts(lines: Vec<Line>, settings: &Settings) -> Vec<String> {
+        // A 1000x1000 crop, so gaps and heights are comparable
+        MyApp::join_split_lines(lines, Vec2::new(1000.0, 1000.0), settings).into_iter().map(|line| line.text).collect()
+    }
+
+    #[test]
+    fn join_split_line() {
+        let settings = Settings::default();
+        let lines = vec![
+            line("The quick brown", 0.1, 0.10, 0.4, 0.12),
+            line("fox jumps", 0.41, 0.101, 0.6, 0.121),
+            line("over the lazy dog", 0.1, 0.13, 0.5, 0.15),
+        ];
+        let joined = MyApp::join_split_lines(lines, Vec2::new(1000.0, 1000.0), &settings);
+        assert_eq!(joined.iter().map(|line| line.text.as_str()).collect::<Vec<_>>(), ["The quick brown fox jumps", "over the lazy dog"]);
+        assert_eq!(joined[0].bbox, Rect::from_min_max(Pos2::new(0.1, 0.10), Pos2::new(0.6, 0.121)));
+        assert_eq!(joined[0].left, 0.1);
+
+        // And the merged text has them on one line
+        assert_eq!(MyApp::merge_lines(joined, 1000.0, &settings), "The quick brown fox jumps\nover the lazy dog\n");
+    }
+
+    #[test]
+    fn join_split_line_in_several_pieces() {
+        let lines = vec![
+            line("One", 0.1, 0.10, 0.2, 0.12),
+            line("two", 0.21, 0.10, 0.3, 0.12),
+            line("three", 0.31, 0.10, 0.4, 0.12),
+        ];
+        assert_eq!(joined_texts(lines, &Settings::default()), ["One two three"]);
+    }
+
+    #[test]
+    fn dont_join_separate_lines() {
+        let lines = vec![
+            // Stacked lines, with no vertical overlap
+            line("First line", 0.1, 0.10, 0.4, 0.12),
+            line("second line", 0.1, 0.125, 0.4, 0.145),
+            // A neighbouring column, much further away than a word gap
+            line("other column", 0.6, 0.125, 0.9, 0.145),
+            // Overlapping the previous line a bit, but mostly on the next row
+            line("next row", 0.1, 0.14, 0.4, 0.16),
+        ];
+        assert_eq!(joined_texts(lines, &Settings::default()), ["First line", "second line", "other column", "next row"]);
+    }
+
+    #[test]
+    fn join_split_line_right_to_left() {
+        let settings = Settings { right_to_left: true, ..Settings::default() };
+        // Sorted right to left, so the second fragment is to the left of the first
+        let lines = vec![
+            line("first", 0.5, 0.10, 0.8, 0.12),
+            line("second", 0.2, 0.10, 0.49, 0.12),
+        ];
+        assert_eq!(joined_texts(lines.clone(), &settings), ["first second"]);
+        // Reading left to right, they're in the wrong order to be one line
+        assert_eq!(joined_texts(lines, &Settings::default()), ["first", "second"]);
+    }
+
+    #[test]
+    fn join_split_lines_disabled() {
+        let settings = Settings { join_split_lines: false, ..Settings::default() };
+        let lines = vec![line("One", 0.1, 0.10, 0.2, 0.12), line("two", 0.21, 0.10, 0.3, 0.12)];
+        assert_eq!(joined_texts(lines, &settings), ["One", "two"]);
+    }
+
+    // Rendering crops in a loop, as when extracting repeatedly: the buffer is reused as long as the
+    // crop doesn't grow, and the result is the same as rendering into a fresh image
+    #[test]
+    fn render_crop_reuses_buffer() {
+        let image = RgbImage::from_fn(400, 300, |x, y| image::Rgb([x as u8, y as u8, 128]));
+        let crops: Vec<Rect> = (0..50).map(|i| Rect::from_min_size(Pos2::new(i as f32, 10.0), Vec2::new(200.0 - i as f32, 100.0))).collect();
+        let mask = vec![255; 200 * 100];
+
+        let mut out = RgbImage::new(0, 0);
+        let mut buffer = None;
+        for &rect in &crops {
+            MyApp::render_crop(&image, rect, &mask, (10, 240), [48, 48, 48], &mut out);
+            let mut fresh = RgbImage::new(0, 0);
+            MyApp::render_crop(&image, rect, &mask, (10, 240), [48, 48, 48], &mut fresh);
+            assert!(out == fresh, "different image for {:?}", rect);
+
+            let current = (out.as_raw().as_ptr(), out.as_raw().capacity());
+            assert_eq!(*buffer.get_or_insert(current), current, "reallocated for {:?}", rect);
+        }
+    }
+
+    fn scalers() -> Vec<Scaler> {
+        let mut scalers = Vec::new();
+        for scale in [MIN_SCALE, 0.125, 1.0 / 3.0, 1.0, 2.5, MAX_SCALE] {
+            for offset in [Vec2::ZERO, Vec2::new(123.4, -56.7), Vec2::new(-5000.0, 8000.25)] {
+                for origin in [Pos2::ZERO, Pos2::new(300.0, 24.5)] {
+                    let image_rect = Rect::from_min_size(origin, Vec2::new(1600.0, 1000.0));
+                    scalers.push(Scaler { scale, viewport: image_rect.size(), offset, image_rect });
+                }
+            }
+        }
+        scalers
+    }
+
+    fn assert_near(a: Pos2, b: Pos2, tolerance: f32) {
+        assert!((a - b).length() <= tolerance, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn scaler_screen_round_trip() {
+        for scaler in scalers() {
+            for p in [Pos2::ZERO, Pos2::new(1.5, 2.25), Pos2::new(800.0, 500.0), Pos2::new(-40.0, 1999.0)] {
+                let p = scaler.image_rect.min + p.to_vec2();
+                // Screen coords are a few thousand units at most, so a thousandth of a unit is plenty
+                assert_near(scaler.image_to_screen(scaler.screen_to_image(p)), p, 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn scaler_image_round_trip() {
+        for scaler in scalers() {
+            for p in [Pos2::ZERO, Pos2::new(0.5, 0.5), Pos2::new(3000.0, 4000.0), Pos2::new(12345.6, 7.8)] {
+                // Relative to the scale, since one screen unit is many image pixels when zoomed out
+                assert_near(scaler.screen_to_image(scaler.image_to_screen(p)), p, 1e-3 / scaler.scale.min(1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn scaler_origin() {
+        for scaler in scalers() {
+            // The image's top left is offset from the top left of the viewport
+            assert_near(scaler.image_to_screen(Pos2::ZERO), scaler.image_rect.min - scaler.offset, 1e-3);
+            // and a screen-space step is a 1/scale step in the image
+            let a = scaler.screen_to_image(scaler.image_rect.min);
+            let b = scaler.screen_to_image(scaler.image_rect.min + Vec2::new(10.0, 0.0));
+            assert_near(b, a + Vec2::new(10.0 / scaler.scale, 0.0), 1e-2 / scaler.scale.min(1.0));
+        }
+    }
+
+    // Repeatedly mapping a vertex to the screen and back, as dragging does, mustn't walk it away
+    #[test]
+    fn scaler_no_drift() {
+        for scaler in scalers() {
+            let start = Pos2::new(1234.5, 678.9);
+            let mut p = start;
+            for _ in 0..1000 {
+                p = scaler.screen_to_image(scaler.image_to_screen(p));
+            }
+            assert_near(p, start, 1e-2 / scaler.scale.min(1.0));
+        }
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright (c) 2025 Philip Taylor
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// A documented interchange format for the annotations, for scripts to read, kept separate from
+// the internal structs so it doesn't change whenever they do.
+//
+// The YAML export is a Scrapbook, with the pages in the project's image order, and the JSON
+// lines export is the same pages one per line. Coordinates are image pixels, as [x, y] with y
+// down. Optional fields are left out rather than written as empty strings or nulls, and lists
+// are left out when they're empty.
+
+use std::{fs::File, io::{BufWriter, Write}};
+
+use serde::{Deserialize, Serialize};
+
+use eframe::epaint::Pos2;
+
+use crate::State;
+
+// Bumped whenever a change here could break a reader
+pub(crate) const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct Scrapbook {
+    pub(crate) version: u32, // SCHEMA_VERSION
+    pub(crate) pages: Vec<Page>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct Page {
+    pub(crate) image: String, // filename, relative to the project's images folder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) date: Option<String>, // as written on the page, e.g. "12 March 1953"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) summary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) notes: Option<String>, // about the page itself, not part of any article
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) blank: bool, // nothing to annotate, e.g. the back of a page
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) articles: Vec<Article>, // in reading order
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct Article {
+    pub(crate) id: u64, // unique within the scrapbook, and stable across exports
+    pub(crate) text: String, // Markdown-ish: "# " starts a heading, a blank line separates paragraphs
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) verified: bool, // proofread against the scan
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) citation: Option<String>, // the source and the page's date, e.g. "The Times, 12 March 1953"
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) image: bool, // a photo or illustration, with a caption instead of text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) caption: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) crop: Option<String>, // path of a PNG of the region
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) regions: Vec<Region>, // where the text is on the page, in the order it was read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) created_at: Option<u64>, // seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) modified_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct Region {
+    pub(crate) outline: Vec<[f32; 2]>, // polygon vertexes, implicitly closed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) holes: Vec<Vec<[f32; 2]>>, // polygons inside the outline that aren't part of the region
+}
+
+fn non_empty(s: Option<&str>) -> Option<String> {
+    s.filter(|s| !s.trim().is_empty()).map(str::to_owned)
+}
+
+fn points(poly: &[Pos2]) -> Vec<[f32; 2]> {
+    poly.iter().map(|p| [p.x, p.y]).collect()
+}
+
+fn article(article: &crate::Article, date: Option<&str>) -> Article {
+    let citation = [non_empty(article.source.as_deref()), non_empty(date)].into_iter().flatten().collect::<Vec<_>>().join(", ");
+    Article {
+        id: article.id,
+        text: article.text.clone(),
+        verified: article.verified,
+        citation: non_empty(Some(&citation)),
+        image: article.kind == crate::ArticleKind::Image,
+        caption: non_empty(article.caption.as_deref()),
+        crop: article.crop.clone(),
+        regions: article.polys.iter().enumerate().map(|(i, poly)| Region {
+            outline: points(poly),
+            holes: article.holes.get(i).map(|holes| holes.iter().map(|hole| points(hole)).collect()).unwrap_or_default(),
+        }).collect(),
+        created_at: article.created_at,
+        modified_at: article.modified_at,
+    }
+}
+
+// None if the page was never annotated
+fn page(state: &State, filename: &str) -> Option<Page> {
+    let page = state.pages.get(filename).filter(|page| !page.is_empty())?;
+    Some(Page {
+        image: filename.to_owned(),
+        date: non_empty(page.date.as_deref()),
+        summary: non_empty(page.summary.as_deref()),
+        notes: non_empty(Some(&page.notes)),
+        blank: page.blank,
+        tags: page.tags.clone(),
+        articles: page.articles.iter().map(|a| article(a, page.date.as_deref())).collect(),
+    })
+}
+
+// The given pages, skipping any that were never annotated
+pub(crate) fn scrapbook(state: &State, images: &[String]) -> Scrapbook {
+    Scrapbook {
+        version: SCHEMA_VERSION,
+        pages: images.iter().filter_map(|filename| page(state, filename)).collect(),
+    }
+}
+
+pub(crate) fn export_yaml(state: &State, images: &[String], path: &str) -> std::io::Result<usize> {
+    let scrapbook = scrapbook(state, images);
+    let yaml = serde_yaml::to_string(&scrapbook).map_err(std::io::Error::other)?;
+    std::fs::write(path, yaml)?;
+    Ok(scrapbook.pages.len())
+}
+
+// One Page per line as JSON, written as it goes so a huge collection doesn't have to be held in
+// memory twice. There's no version header, so the pages can be concatenated or split freely
+pub(crate) fn export_jsonl(state: &State, images: &[String], path: &str) -> std::io::Result<usize> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut count = 0;
+    for page in images.iter().filter_map(|filename| page(state, filename)) {
+        serde_json::to_writer(&mut out, &page)?;
+        out.write_all(b"\n")?;
+        count += 1;
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> State {
+        let mut state: State = serde_yaml::from_str("images: [a.jpg, b.jpg, c.jpg]\npages: {}\nopen_image: 0\n").unwrap();
+        let page = state.page_named("a.jpg".to_owned());
+        page.date = Some("12 March 1953".to_owned());
+        page.tags.push("sports".to_owned());
+        page.articles.push(crate::Article {
+            id: 7,
+            polys: vec![vec![Pos2::new(1.0, 2.0), Pos2::new(30.0, 2.0), Pos2::new(30.0, 40.5)]],
+            text: "# Headline\nBody".to_owned(),
+            raw_ocr: Vec::new(),
+            holes: vec![vec![vec![Pos2::new(5.0, 5.0), Pos2::new(6.0, 5.0), Pos2::new(6.0, 6.0)]]],
+            provenance: Vec::new(),
+            verified: true,
+            source: Some("The Times".to_owned()),
+            kind: crate::ArticleKind::Text,
+            caption: None,
+            crop: None,
+            created_at: Some(100),
+            modified_at: None,
+        });
+        state.page_named("b.jpg".to_owned());
+        state
+    }
+
+    #[test]
+    fn leaves_out_empty_fields() {
+        let yaml = serde_yaml::to_string(&scrapbook(&state(), &["a.jpg".to_owned(), "b.jpg".to_owned()])).unwrap();
+        assert_eq!(yaml, "\
+version: 1
+pages:
+- image: a.jpg
+  date: 12 March 1953
+  tags:
+  - sports
+  articles:
+  - id: 7
+    text: |-
+      # Headline
+      Body
+    verified: true
+    citation: The Times, 12 March 1953
+    regions:
+    - outline:
+      - - 1.0
+        - 2.0
+      - - 30.0
+        - 2.0
+      - - 30.0
+        - 40.5
+      holes:
+      - - - 5.0
+          - 5.0
+        - - 6.0
+          - 5.0
+        - - 6.0
+          - 6.0
+    created_at: 100
+");
+    }
+
+    #[test]
+    fn round_trips() {
+        let scrapbook = scrapbook(&state(), &["c.jpg".to_owned(), "a.jpg".to_owned()]);
+        let yaml = serde_yaml::to_string(&scrapbook).unwrap();
+        assert_eq!(serde_yaml::from_str::<Scrapbook>(&yaml).unwrap(), scrapbook);
+    }
+}